@@ -1,4 +1,4 @@
-use crate::{interval::{self, *}, ray::Ray, vec3::Vec3};
+use crate::{interval::*, ray::Ray, vec3::Vec3};
 
 #[derive(Default)]
 pub struct AABB {
@@ -85,8 +85,4 @@ impl AABB {
     pub fn empty() -> Self {
         Self::new(Interval::EMPTY(), Interval::EMPTY(), Interval::EMPTY())
     }
-
-    pub fn universe() -> Self {
-        Self::new(Interval::UNIVERSE(), Interval::UNIVERSE(), Interval::UNIVERSE())
-    }
 }
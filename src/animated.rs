@@ -0,0 +1,217 @@
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use crate::aabb::AABB;
+use crate::animation::{catmull_rom, lerp, Interpolation};
+use crate::hittable::*;
+use crate::interval::Interval;
+use crate::libs::*;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// pose of an object at one point in time (seconds). rotation is euler
+// angles in degrees, applied x, then y, then z. scale is per axis and
+// happens before the rotation, both about the object's own origin
+#[derive(Clone, Copy, Debug)]
+pub struct TransformKey {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: Vec3,
+}
+
+impl TransformKey {
+    pub fn new(time: f64) -> Self {
+        Self { time, translation: Vec3::default(), rotation: Vec3::default(), scale: Vec3::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn translate(mut self, offset: Vec3) -> Self {
+        self.translation = offset;
+        self
+    }
+
+    pub fn rotate(mut self, degrees: Vec3) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+// TransformKey resolved into a rotation basis
+struct Transform {
+    translation: Vec3,
+    // columns of the rotation matrix
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    scale: Vec3,
+}
+
+impl Transform {
+    fn from_key(key: &TransformKey) -> Self {
+        let (sx, cx) = deg_to_rad(key.rotation.x()).sin_cos();
+        let (sy, cy) = deg_to_rad(key.rotation.y()).sin_cos();
+        let (sz, cz) = deg_to_rad(key.rotation.z()).sin_cos();
+
+        // rz * ry * rx
+        Self {
+            translation: key.translation,
+            x: Vec3::new(cy*cz, cy*sz, -sy),
+            y: Vec3::new(sx*sy*cz - cx*sz, sx*sy*sz + cx*cz, sx*cy),
+            z: Vec3::new(cx*sy*cz + sx*sz, cx*sy*sz - sx*cz, cx*cy),
+            scale: key.scale,
+        }
+    }
+
+    fn rotate(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.x + v.y() * self.y + v.z() * self.z
+    }
+
+    fn unrotate(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.x.dot(v), self.y.dot(v), self.z.dot(v))
+    }
+
+    fn point_to_world(&self, p: &Vec3) -> Vec3 {
+        self.rotate(&(*p * self.scale)) + self.translation
+    }
+
+    fn point_to_object(&self, p: &Vec3) -> Vec3 {
+        self.vector_to_object(&(*p - self.translation))
+    }
+
+    fn vector_to_world(&self, v: &Vec3) -> Vec3 {
+        self.rotate(&(*v * self.scale))
+    }
+
+    fn vector_to_object(&self, v: &Vec3) -> Vec3 {
+        let v = self.unrotate(v);
+        Vec3::new(v.x() / self.scale.x(), v.y() / self.scale.y(), v.z() / self.scale.z())
+    }
+
+    // inverse transpose, keeps normals perpendicular under non-uniform scale
+    fn normal_to_world(&self, n: &Vec3) -> Vec3 {
+        let n = Vec3::new(n.x() / self.scale.x(), n.y() / self.scale.y(), n.z() / self.scale.z());
+        self.rotate(&n).unit_vector()
+    }
+}
+
+// moves any hittable along keyframed transforms, evaluated at the ray's
+// time. the wrapped object is modelled around the origin
+pub struct Animated {
+    object: Rc<dyn Hittable>,
+    keys: Vec<TransformKey>,
+    interpolation: Interpolation,
+    bbox: AABB,
+}
+
+impl Animated {
+    pub fn new(object: Rc<dyn Hittable>, mut keys: Vec<TransformKey>, interpolation: Interpolation) -> Self {
+        assert!(!keys.is_empty(), "animated object needs at least one keyframe");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut animated = Self { object, keys, interpolation, bbox: AABB::default() };
+        animated.bbox = animated.motion_bounds();
+        animated
+    }
+
+    fn sample(&self, time: f64) -> TransformKey {
+        let n = self.keys.len();
+        if n == 1 || time <= self.keys[0].time { return self.keys[0]; }
+        if time >= self.keys[n - 1].time { return self.keys[n - 1]; }
+
+        let i = self.keys.partition_point(|k| k.time <= time) - 1;
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let u = (time - k1.time) / (k2.time - k1.time);
+
+        match self.interpolation {
+            Interpolation::Linear => TransformKey {
+                time,
+                translation: lerp(k1.translation, k2.translation, u),
+                rotation: lerp(k1.rotation, k2.rotation, u),
+                scale: lerp(k1.scale, k2.scale, u),
+            },
+            Interpolation::Spline => {
+                let k0 = if i > 0 { &self.keys[i - 1] } else { k1 };
+                let k3 = if i + 2 < n { &self.keys[i + 2] } else { k2 };
+                TransformKey {
+                    time,
+                    translation: catmull_rom(k0.translation, k1.translation, k2.translation, k3.translation, u),
+                    rotation: catmull_rom(k0.rotation, k1.rotation, k2.rotation, k3.rotation, u),
+                    scale: catmull_rom(k0.scale, k1.scale, k2.scale, k3.scale, u),
+                }
+            }
+        }
+    }
+
+    // union of the transformed object box over the whole animation. the
+    // motion is sampled densely, and the box padded a little for the arcs
+    // rotating corners sweep between samples
+    fn motion_bounds(&self) -> AABB {
+        let inner = self.object.bounding_box();
+        let corners: Vec<Vec3> = (0..8).map(|c| Vec3::new(
+            if c & 1 == 0 { inner.x.min } else { inner.x.max },
+            if c & 2 == 0 { inner.y.min } else { inner.y.max },
+            if c & 4 == 0 { inner.z.min } else { inner.z.max },
+        )).collect();
+
+        let steps_per_key = 32;
+        let (start, end) = (self.keys[0].time, self.keys[self.keys.len() - 1].time);
+        let steps = steps_per_key * self.keys.len();
+
+        let mut lo = Vec3::new(INFINITY, INFINITY, INFINITY);
+        let mut hi = -lo;
+        for step in 0..=steps {
+            let time = start + (end - start) * step as f64 / steps as f64;
+            let xf = Transform::from_key(&self.sample(time));
+            for corner in &corners {
+                let p = xf.point_to_world(corner);
+                lo = Vec3::new(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z()));
+                hi = Vec3::new(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z()));
+            }
+        }
+
+        let pad = (hi - lo).length() * 0.01;
+        let pad = Vec3::new(pad, pad, pad);
+        AABB::from_points(lo - pad, hi + pad)
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let xf = Transform::from_key(&self.sample(r.time()));
+
+        // the direction is not normalized so t is the same in both spaces
+        let local = r.spawn(xf.point_to_object(&r.origin()), xf.vector_to_object(&r.direction()));
+        if !self.object.hit(&local, ray_t, rec) {
+            return false;
+        }
+
+        rec.p = xf.point_to_world(&rec.p);
+        rec.normal = xf.normal_to_world(&rec.normal);
+        rec.geometric_normal = xf.normal_to_world(&rec.geometric_normal);
+        rec.dpdu = xf.vector_to_world(&rec.dpdu);
+        rec.dpdv = xf.vector_to_world(&rec.dpdv);
+        return true;
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"animated");
+        self.object.hash_into(state);
+        state.write_usize(self.keys.len());
+        for key in &self.keys {
+            state.write_u64(key.time.to_bits());
+            for v in [key.translation, key.rotation, key.scale] {
+                v.hash_into(state);
+            }
+        }
+        state.write_u8(self.interpolation as u8);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{stderr, BufWriter, Write, Error};
+use std::ops::{Add, Mul, Sub, RangeInclusive};
+use std::path::Path;
+
+use crate::camera::{self, Camera};
+use crate::denoise::Denoiser;
+use crate::hittable::Hittable;
+use crate::libs::*;
+use crate::vec3::Vec3;
+
+// camera settings at one point in time (seconds)
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f64,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+    pub defocus_angle: f64,
+}
+
+impl Keyframe {
+    pub fn from_camera(time: f64, cam: &Camera) -> Self {
+        Self {
+            time,
+            lookfrom: cam.lookfrom,
+            lookat: cam.lookat,
+            vfov: cam.vfov,
+            focus_dist: cam.focus_dist,
+            defocus_angle: cam.defocus_angle,
+        }
+    }
+
+    pub fn apply(&self, cam: &mut Camera) {
+        cam.lookfrom = self.lookfrom;
+        cam.lookat = self.lookat;
+        cam.vfov = self.vfov;
+        cam.focus_dist = self.focus_dist;
+        cam.defocus_angle = self.defocus_angle;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Interpolation {
+    Linear,
+    // catmull-rom through every keyframe
+    #[default]
+    Spline,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "spline" => Some(Interpolation::Spline),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CameraAnimation {
+    // sorted by time
+    keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+    // last keyframe is the same as the first, the spline wraps around and
+    // the last frame is left out so the sequence can be played in a loop
+    pub looping: bool,
+}
+
+impl CameraAnimation {
+    pub fn add(&mut self, keyframe: Keyframe) {
+        let idx = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(idx, keyframe);
+    }
+
+    // one full orbit of lookfrom around lookat (about vup) in the given time
+    pub fn turntable(cam: &Camera, seconds: f64) -> Self {
+        let mut animation = Self { looping: true, ..Self::default() };
+
+        let up = cam.vup.unit_vector();
+        let offset = cam.lookfrom - cam.lookat;
+        // enough keys that the spline stays close to a circle
+        let steps = 16;
+        for step in 0..=steps {
+            let angle = 2.0 * PI * step as f64 / steps as f64;
+            let (sin, cos) = angle.sin_cos();
+            let rotated = offset * cos + up.cross(&offset) * sin + up * (up.dot(&offset) * (1.0 - cos));
+
+            let mut key = Keyframe::from_camera(seconds * step as f64 / steps as f64, cam);
+            key.lookfrom = cam.lookat + rotated;
+            animation.add(key);
+        }
+
+        animation
+    }
+
+    pub fn duration(&self) -> f64 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    // frames are numbered from 1, frame n is shown at (n - 1) / fps
+    pub fn frame_count(&self, fps: f64) -> i32 {
+        let frames = (self.duration() * fps).round() as i32;
+        if self.looping { frames.max(1) } else { frames + 1 }
+    }
+
+    pub fn sample(&self, time: f64) -> Option<Keyframe> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        let n = self.keyframes.len();
+
+        if n == 1 || time <= first.time { return Some(Keyframe { time, ..*first }); }
+        if time >= last.time { return Some(Keyframe { time, ..*last }); }
+
+        // segment i goes from keyframes[i] to keyframes[i + 1]
+        let i = self.keyframes.partition_point(|k| k.time <= time) - 1;
+        let (k1, k2) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let u = (time - k1.time) / (k2.time - k1.time);
+
+        let key = match self.interpolation {
+            Interpolation::Linear => Keyframe {
+                time,
+                lookfrom: lerp(k1.lookfrom, k2.lookfrom, u),
+                lookat: lerp(k1.lookat, k2.lookat, u),
+                vfov: lerp(k1.vfov, k2.vfov, u),
+                focus_dist: lerp(k1.focus_dist, k2.focus_dist, u),
+                defocus_angle: lerp(k1.defocus_angle, k2.defocus_angle, u),
+            },
+            Interpolation::Spline => {
+                // neighbours past the ends are the end keys themselves,
+                // or wrap around (skipping the duplicate) when looping
+                let k0 = if i > 0 { &self.keyframes[i - 1] }
+                         else if self.looping && n > 2 { &self.keyframes[n - 2] }
+                         else { k1 };
+                let k3 = if i + 2 < n { &self.keyframes[i + 2] }
+                         else if self.looping && n > 2 { &self.keyframes[1] }
+                         else { k2 };
+
+                Keyframe {
+                    time,
+                    lookfrom: catmull_rom(k0.lookfrom, k1.lookfrom, k2.lookfrom, k3.lookfrom, u),
+                    lookat: catmull_rom(k0.lookat, k1.lookat, k2.lookat, k3.lookat, u),
+                    vfov: catmull_rom(k0.vfov, k1.vfov, k2.vfov, k3.vfov, u),
+                    // never let these overshoot below zero
+                    focus_dist: f64::max(catmull_rom(k0.focus_dist, k1.focus_dist, k2.focus_dist, k3.focus_dist, u), 1e-3),
+                    defocus_angle: f64::max(catmull_rom(k0.defocus_angle, k1.defocus_angle, k2.defocus_angle, k3.defocus_angle, u), 0.0),
+                }
+            }
+        };
+
+        Some(key)
+    }
+}
+
+pub fn lerp<T>(a: T, b: T, u: f64) -> T
+where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T> {
+    a + (b - a) * u
+}
+
+pub fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, u: f64) -> T
+where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T> {
+    let (u2, u3) = (u * u, u * u * u);
+    (p1 * 2.0
+        + (p2 - p0) * u
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * u2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * u3) * 0.5
+}
+
+// <stem>_0001.<ext> for output path <stem>.<ext>
+pub fn frame_path(output: &str, frame: i32) -> String {
+    match output.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => format!("{}_{:04}.{}", stem, frame, ext),
+        _ => format!("{}_{:04}", output, frame),
+    }
+}
+
+// renders every frame in the range to its own numbered file. with
+// skip_existing, frames whose file is already there are not rendered again,
+// so an interrupted sequence can be restarted with the same command. a
+// checkpoint path is numbered the same way
+#[allow(clippy::too_many_arguments)]
+pub fn render_sequence<H: Hittable>(cam: &mut Camera, world: &H, animation: &CameraAnimation,
+                                    frames: RangeInclusive<i32>, fps: f64, output: &str,
+                                    skip_existing: bool, denoiser: Option<&Denoiser>) -> Result<(), Error> {
+    let frame_count = frames.clone().count();
+    let checkpoint = cam.checkpoint.clone();
+
+    for (n, frame) in frames.enumerate() {
+        if camera::interrupted() { break; }
+
+        let path = frame_path(output, frame);
+        if skip_existing && Path::new(&path).exists() {
+            stderr().write_all(format!("Frame {} ({}/{}) exists, skipping\n", frame, n + 1, frame_count).as_bytes())?;
+            continue;
+        }
+
+        let Some(key) = animation.sample((frame - 1) as f64 / fps) else { break; };
+        key.apply(cam);
+        cam.time = key.time;
+        cam.checkpoint = checkpoint.as_deref().map(|path| frame_path(path, frame));
+
+        stderr().write_all(format!("Frame {} ({}/{}) t = {:.3}s\n", frame, n + 1, frame_count, key.time).as_bytes())?;
+        let film = cam.render(world)?;
+        // a frame cut short by ctrl-c is not finished, leave it for the next run
+        if camera::interrupted() { break; }
+
+        let film = match (denoiser, cam.aovs()) {
+            (Some(denoiser), Some(aovs)) => denoiser.denoise(&film, aovs),
+            _ => film,
+        };
+
+        // write next to the final name first so a half written file never
+        // looks like a finished frame
+        let tmp_path = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        film.write_ppm(&mut out, cam.display_exposure(), cam.tone_map)?;
+        out.flush()?;
+        drop(out);
+        std::fs::rename(&tmp_path, &path)?;
+    }
+
+    cam.checkpoint = checkpoint;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write, Error};
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// first-hit arbitrary output variables. normal, albedo, depth and position
+// are averaged over every primary ray of a pixel, the ids come from the
+// first primary ray that hit something
+pub struct Aovs {
+    pub width: i32,
+    pub height: i32,
+    normal: Vec<Vec3>,
+    albedo: Vec<Color>,
+    depth: Vec<f64>,
+    position: Vec<Vec3>,
+    // primary rays that hit something
+    hits: Vec<u32>,
+    object_id: Vec<u32>,
+    // material identity (address of the shared material), numbered on output
+    material: Vec<usize>,
+}
+
+impl Aovs {
+    pub fn new(width: i32, height: i32) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            width, height,
+            normal: vec![Vec3::default(); pixel_count],
+            albedo: vec![Color::default(); pixel_count],
+            depth: vec![0.0; pixel_count],
+            position: vec![Vec3::default(); pixel_count],
+            hits: vec![0; pixel_count],
+            object_id: vec![0; pixel_count],
+            material: vec![0; pixel_count],
+        }
+    }
+
+    fn index(&self, i: i32, j: i32) -> usize {
+        (j * self.width + i) as usize
+    }
+
+    pub fn add_hit(&mut self, i: i32, j: i32, r: &Ray, rec: &HitRecord) {
+        let idx = self.index(i, j);
+
+        self.normal[idx] += rec.normal;
+        self.albedo[idx] += rec.mat.albedo(rec);
+        // distance along the ray, independent of the direction's length
+        self.depth[idx] += rec.t * r.direction().length();
+        self.position[idx] += rec.p;
+
+        if self.hits[idx] == 0 {
+            self.object_id[idx] = rec.object_id;
+            self.material[idx] = std::rc::Rc::as_ptr(&rec.mat) as *const () as usize;
+        }
+        self.hits[idx] += 1;
+    }
+
+    fn average(&self, idx: usize) -> f64 {
+        if self.hits[idx] == 0 { 0.0 } else { 1.0 / self.hits[idx] as f64 }
+    }
+
+    pub fn normal(&self, i: i32, j: i32) -> Vec3 {
+        let idx = self.index(i, j);
+        let n = self.normal[idx];
+        if n.near_zero() { n } else { n.unit_vector() }
+    }
+
+    pub fn albedo(&self, i: i32, j: i32) -> Color {
+        let idx = self.index(i, j);
+        self.albedo[idx] * self.average(idx)
+    }
+
+    pub fn depth(&self, i: i32, j: i32) -> f64 {
+        let idx = self.index(i, j);
+        self.depth[idx] * self.average(idx)
+    }
+
+    pub fn position(&self, i: i32, j: i32) -> Vec3 {
+        let idx = self.index(i, j);
+        self.position[idx] * self.average(idx)
+    }
+
+    // 0 where nothing was hit
+    pub fn object_id(&self, i: i32, j: i32) -> u32 {
+        self.object_id[self.index(i, j)]
+    }
+
+    // materials numbered from 1 in the order they first appear in the image
+    fn material_ids(&self) -> Vec<u32> {
+        let mut ids = HashMap::new();
+        let mut next = 1;
+        (0..self.material.len()).map(|idx| {
+            if self.hits[idx] == 0 { return 0; }
+            *ids.entry(self.material[idx]).or_insert_with(|| { next += 1; next - 1 })
+        }).collect()
+    }
+
+    // writes <prefix>_<aov>.pfm for every buffer
+    pub fn write_all(&self, prefix: &str) -> Result<(), Error> {
+        let w = self.width;
+        let material_ids = self.material_ids();
+
+        self.write_pfm(&format!("{}_normal.pfm", prefix), |i, j| self.normal(i, j))?;
+        self.write_pfm(&format!("{}_albedo.pfm", prefix), |i, j| self.albedo(i, j))?;
+        self.write_pfm(&format!("{}_depth.pfm", prefix), |i, j| Vec3::new(self.depth(i, j), self.depth(i, j), self.depth(i, j)))?;
+        self.write_pfm(&format!("{}_position.pfm", prefix), |i, j| self.position(i, j))?;
+        self.write_pfm(&format!("{}_object_id.pfm", prefix), |i, j| {
+            let id = self.object_id(i, j) as f64;
+            Vec3::new(id, id, id)
+        })?;
+        self.write_pfm(&format!("{}_material_id.pfm", prefix), |i, j| {
+            let id = material_ids[(j * w + i) as usize] as f64;
+            Vec3::new(id, id, id)
+        })?;
+
+        Ok(())
+    }
+
+    // portable float map, rows are stored bottom to top
+    fn write_pfm(&self, path: &str, value: impl Fn(i32, i32) -> Vec3) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(format!("PF\n{} {}\n-1.0\n", self.width, self.height).as_bytes())?;
+
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                let v = value(i, j);
+                for c in [v.x(), v.y(), v.z()] {
+                    out.write_all(&(c as f32).to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+}
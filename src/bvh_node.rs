@@ -1,14 +1,13 @@
 use std::cmp::Ordering;
+use std::hash::Hasher;
 use std::rc::Rc;
 
 use crate::aabb::*;
 use crate::hittable::*;
 use crate::hittable_list::*;
-use crate::vec3::*;
 use crate::ray::*;
 use crate::interval::*;
-use crate::libs::*;
-use crate::hittable_list::*;
+use crate::stats;
 
 pub struct BvhNode {
     left:  Rc<dyn Hittable>,
@@ -24,8 +23,8 @@ impl BvhNode {
 
     pub fn new(objects: &mut Vec<Rc<dyn Hittable>>, start: usize, end: usize) -> Self {
         let mut bbox = AABB::empty();
-        for obj_idx in start..end {
-            bbox = AABB::from_boxes(&bbox, objects[obj_idx].bounding_box());
+        for object in &objects[start..end] {
+            bbox = AABB::from_boxes(&bbox, object.bounding_box());
         }
         let axis = bbox.longest_axis();
 
@@ -75,6 +74,7 @@ impl BvhNode {
 }
 impl Hittable for BvhNode {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::count_bvh_node();
         if !self.bbox.hit(r, ray_t) {
             return false;
         }
@@ -88,4 +88,10 @@ impl Hittable for BvhNode {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"bvh");
+        self.left.hash_into(state);
+        self.right.hash_into(state);
+    }
 }
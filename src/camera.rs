@@ -1,8 +1,11 @@
-use core::time;
-use std::thread;
-use std::io::{stdout, stderr, Write};
+use std::io::{stderr, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::hash::Hasher;
 use std::time::*;
-use show_image::{ImageView, ImageInfo, create_window};
+use show_image::{ImageView, ImageInfo, WindowOptions, WindowProxy, create_window};
+use show_image::event::WindowEvent;
 
 use crate::color::Color;
 use crate::ray::Ray;
@@ -12,7 +15,34 @@ use crate::HitRecord;
 use crate::Interval;
 use crate::libs;
 use crate::libs::*;
-use crate::Metal;
+use crate::filter::*;
+use crate::film::Film;
+use crate::tonemap::ToneMap;
+use crate::checkpoint::Checkpoint;
+use crate::stats;
+use crate::aov::Aovs;
+use crate::projection::*;
+use crate::lens::PhysicalLens;
+use crate::spectrum;
+
+// minimum time between preview updates in scanline mode
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(250);
+
+// set by the ctrl-c handler, renders stop after the current pass / scanline
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install_interrupt_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        // a second ctrl-c aborts without writing anything
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    })
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
 
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -24,12 +54,50 @@ pub struct Camera {
     pub lookfrom: Vec3,
     pub lookat: Vec3,
     pub vup: Vec3,
+    pub projection: Projection,
     // defocus blur
     pub defocus_angle: f64,
     pub focus_dist: f64,
-
+    // aperture shape, 0 blades is a round aperture
+    pub aperture_blades: u32,
+    // degrees
+    pub aperture_rotation: f64,
+    // real lens settings, used instead of vfov, defocus_angle and shutter
+    // and for the exposure
+    pub lens: Option<PhysicalLens>,
+    // scene time of the frame and how long the shutter stays open after
+    // it, both in seconds. rays are spread over the interval for motion blur
+    pub time: f64,
+    pub shutter: f64,
+    // focus on whatever is seen through this pixel instead of focus_dist
+    pub autofocus: Option<(i32, i32)>,
+    // reconstruction filter samples are splatted with
+    pub filter: Rc<dyn Filter>,
+    // display transform, only applied to 8-bit output
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    // render whole-frame passes of 1 spp instead of full-spp scanlines
+    pub progressive: bool,
+    // stop early once this much time has passed
+    pub time_limit: Option<Duration>,
+    // periodically save the film + rng state here (renders in passes)
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Duration,
+    // identifies the scene in checkpoints, see Scene::hash
+    pub scene_hash: u64,
+    // collect first-hit aovs alongside the beauty render
+    pub record_aovs: bool,
+    // trace one wavelength per sample instead of rgb, for dispersion
+    pub spectral: bool,
+
+    // set by initialize, the derived fields below are garbage before
+    initialized: bool,
     img_height: i32,
-    pixel_samples_scale: f64,
+    // the settings as rendered, after the lens and autofocus
+    active_vfov: f64,
+    active_defocus_angle: f64,
+    active_focus_dist: f64,
+    active_shutter: f64,
     center: Vec3,
     pixel00_loc: Vec3,
     pixel_delta_u: Vec3,
@@ -41,6 +109,12 @@ pub struct Camera {
     // defocus
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    // preview window, kept so it can outlive the render
+    window: Option<WindowProxy>,
+    window_events: Option<Receiver<WindowEvent>>,
+    window_closed: bool,
+    // aovs of the last render, if recorded
+    aovs: Option<Aovs>,
 }
 
 impl Default for Camera {
@@ -55,12 +129,37 @@ impl Default for Camera {
             lookfrom: Vec3::new(0.0, 0.0, 0.0),
             lookat: Vec3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
+            projection: Projection::default(),
             // blur
             defocus_angle: 0.0,
             focus_dist: 10.0,
-
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            lens: None,
+            time: 0.0,
+            shutter: 0.0,
+            autofocus: None,
+            // filter
+            filter: Rc::new(BoxFilter::default()),
+            // display
+            exposure: 0.0,
+            tone_map: ToneMap::default(),
+            // progressive
+            progressive: false,
+            time_limit: None,
+            // checkpoints
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(600),
+            scene_hash: 0,
+            record_aovs: false,
+            spectral: false,
+
+            initialized: false,
             img_height: i32::default(),
-            pixel_samples_scale: f64::default(),
+            active_vfov: 0.0,
+            active_defocus_angle: 0.0,
+            active_focus_dist: 0.0,
+            active_shutter: 0.0,
             center: Vec3::default(),
             pixel00_loc: Vec3::default(),
             pixel_delta_u: Vec3::default(),
@@ -72,93 +171,334 @@ impl Default for Camera {
             // defocus disk basis vecs
             defocus_disk_u: Vec3::default(),
             defocus_disk_v: Vec3::default(),
+            window: None,
+            window_events: None,
+            window_closed: false,
+            aovs: None,
         }
     }
 }
 
 impl Camera {
-    pub fn render<H: Hittable>(&mut self, world: &H) -> Result<(), std::io::Error> {
-        self.initialize(); 
-
-        let mut data = vec![0; 3 * self.img_height as usize * self.img_width as usize];
+    pub fn render<H: Hittable>(&mut self, world: &H) -> Result<Film, std::io::Error> {
+        self.initialize(world);
+        let film = Film::new(self.img_width, self.img_height);
+        self.render_onto(world, film)
+    }
 
-        /*
-        print!("P3\n{} {}\n255\n", self.img_width, self.img_height);
+    // keep accumulating samples into an existing film (e.g. one loaded
+    // from disk), which must match the camera's image size
+    pub fn render_onto<H: Hittable>(&mut self, world: &H, mut film: Film) -> Result<Film, std::io::Error> {
+        self.initialize(world);
+        if film.width != self.img_width || film.height != self.img_height {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "film size does not match camera"));
+        }
 
         let mut err = stderr();
-        let mut out = stdout();
-        for j in 0..self.img_height {
-            let buff = format!("\rScanlines remaining: {} ", self.img_height-j);
-            err.write(buff.as_bytes())?;
-            for i in 0..self.img_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _sample in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j); 
-                    pixel_color += Self::ray_color(&r, self.max_depth, world);
+        self.display(&film);
+        let mut aovs = if self.record_aovs { Some(Aovs::new(self.img_width, self.img_height)) } else { None };
+
+        let start_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let start = Instant::now();
+        let mut last_checkpoint = Instant::now();
+        // stopped before all samples were taken
+        let mut stopped = false;
+        if self.progressive || self.checkpoint.is_some() {
+            // a resumed film counts towards the target spp
+            let first_pass = film.effective_spp() as i32;
+
+            for pass in first_pass..self.samples_per_pixel {
+                self.render_pass(world, &mut film, &mut aovs);
+
+                let buff = format!("\rPass {}/{} ", pass+1, self.samples_per_pixel);
+                err.write_all(buff.as_bytes())?;
+                self.display(&film);
+
+                if let Some(path) = &self.checkpoint {
+                    if last_checkpoint.elapsed() >= self.checkpoint_interval {
+                        Checkpoint::save(path, self.scene_hash, &film)?;
+                        last_checkpoint = Instant::now();
+                    }
                 }
 
-                pixel_color = pixel_color * self.pixel_samples_scale;
-                pixel_color.write_color(&mut out);
+                if pass + 1 < self.samples_per_pixel && self.should_stop(start) {
+                    stopped = true;
+                    break;
+                }
+            }
+
+            // always leave a checkpoint of the final state behind
+            if let Some(path) = &self.checkpoint {
+                Checkpoint::save(path, self.scene_hash, &film)?;
+            }
+        } else {
+            let mut last_display = Instant::now();
+            let mut rows_done = self.img_height;
+            for j in 0..self.img_height {
+                let buff = format!("\rScanlines remaining: {} ", self.img_height-j);
+                err.write_all(buff.as_bytes())?;
+                for i in 0..self.img_width {
+                    for _sample in 0..self.samples_per_pixel {
+                        self.sample_pixel(i, j, world, &mut film, &mut aovs);
+                    }
+                }
+
+                // tone mapping the whole film is too slow to do every scanline
+                if last_display.elapsed() >= PREVIEW_INTERVAL || j == self.img_height-1 {
+                    self.display(&film);
+                    last_display = Instant::now();
+                }
+
+                if j < self.img_height-1 && self.should_stop(start) {
+                    stopped = true;
+                    rows_done = j + 1;
+                    break;
+                }
+            }
+
+            // the rows left get one sample per pixel, like a progressive
+            // pass, so the partial image and its effective spp cover the
+            // whole frame instead of leaving it black
+            if stopped {
+                for j in rows_done..self.img_height {
+                    for i in 0..self.img_width {
+                        self.sample_pixel(i, j, world, &mut film, &mut aovs);
+                    }
+                }
+                self.display(&film);
             }
         }
-        err.write(b"\rDone.                 \n")?;
-        */
 
-        let mut err = stderr();
-        let window = create_window("image", Default::default()).unwrap();
+        let end_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let status = if stopped { "Interrupted!" } else { "Done!" };
+        err.write_all(format!{"\r{:<22}\nExecution took: {:?}\nEffective spp: {:.2}\n",
+                          status, end_time - start_time, film.effective_spp()}.as_bytes())?;
 
-        let start_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let mut data_idx = 0;
+        self.aovs = aovs;
+        Ok(film)
+    }
+
+    pub fn aovs(&self) -> Option<&Aovs> {
+        self.aovs.as_ref()
+    }
+
+    // feeds every setting that changes the rendered image into the hasher.
+    // samples_per_pixel and the display transform are left out so a
+    // checkpoint can be resumed to a higher spp or with another exposure
+    pub fn hash_settings(&self, hasher: &mut impl Hasher) {
+        hasher.write_u64(self.aspect_ratio.to_bits());
+        hasher.write_i32(self.img_width);
+        hasher.write_i32(self.max_depth);
+        hasher.write_u64(self.vfov.to_bits());
+        for v in [self.lookfrom, self.lookat, self.vup] {
+            for axis in 0..3 {
+                hasher.write_u64(v[axis].to_bits());
+            }
+        }
+        hasher.write(format!("{:?}", self.projection).as_bytes());
+        hasher.write_u64(self.defocus_angle.to_bits());
+        hasher.write_u64(self.focus_dist.to_bits());
+        hasher.write_u64(self.time.to_bits());
+        hasher.write_u64(self.shutter.to_bits());
+        hasher.write_u32(self.aperture_blades);
+        hasher.write_u64(self.aperture_rotation.to_bits());
+        if let Some(lens) = &self.lens {
+            for x in [lens.focal_length, lens.sensor_width, lens.f_number] {
+                hasher.write_u64(x.to_bits());
+            }
+        }
+        if let Some((i, j)) = self.autofocus {
+            hasher.write_i32(i);
+            hasher.write_i32(j);
+        }
+        self.filter.hash_into(hasher);
+        hasher.write_u8(self.spectral as u8);
+    }
+
+    // block until the preview window is closed. returns straight away if
+    // the window is already gone or the render was interrupted
+    pub fn wait_for_window(&mut self) {
+        if let Some(window) = self.window.take() {
+            if !interrupted() {
+                let _ = window.wait_until_destroyed();
+            }
+        }
+    }
+
+    // time limit reached, ctrl-c pressed or preview window closed
+    pub fn should_stop(&mut self, start: Instant) -> bool {
+        if interrupted() { return true; }
+        if self.time_limit.is_some_and(|limit| start.elapsed() >= limit) { return true; }
+
+        if let Some(events) = &self.window_events {
+            while let Ok(event) = events.try_recv() {
+                if let WindowEvent::CloseRequested(_) | WindowEvent::Destroyed(_) = event {
+                    self.window = None;
+                    self.window_events = None;
+                    self.window_closed = true;
+                    return true;
+                }
+            }
+        }
+        self.window_closed
+    }
+
+    // one sample for every pixel of the image
+    pub fn render_pass<H: Hittable>(&self, world: &H, film: &mut Film, aovs: &mut Option<Aovs>) {
         for j in 0..self.img_height {
-            let buff = format!("\rScanlines remaining: {} ", self.img_height-j);
-            err.write(buff.as_bytes())?;
             for i in 0..self.img_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                self.sample_pixel(i, j, world, film, aovs);
+            }
+        }
+    }
+
+    // render every pixel in [x0, x1) x [y0, y1) at full spp. the film covers
+    // the whole image since samples near the tile edge splat outside it
+    pub fn render_tile<H: Hittable>(&self, world: &H, film: &mut Film, x0: i32, y0: i32, x1: i32, y1: i32) {
+        for j in y0..y1 {
+            for i in x0..x1 {
                 for _sample in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j); 
-                    pixel_color += Self::ray_color(&r, self.max_depth, world);
+                    self.sample_pixel(i, j, world, film, &mut None);
                 }
+            }
+        }
+    }
 
-                pixel_color = pixel_color * self.pixel_samples_scale;
+    // trace one jittered sample through pixel i, j and add it to the film
+    fn sample_pixel<H: Hittable>(&self, i: i32, j: i32, world: &H, film: &mut Film, aovs: &mut Option<Aovs>) {
+        let offset = Self::sample_square();
+        let (x, y) = (i as f64 + 0.5 + offset.x(), j as f64 + 0.5 + offset.y());
+        let Some(r) = self.get_ray(i, j, &offset) else {
+            film.add_sample(x, y, &Color::default(), self.filter.as_ref());
+            return;
+        };
+        stats::count_ray(true);
+
+        let lambda = self.spectral.then(spectrum::sample_wavelength);
+        let r = match lambda {
+            Some(lambda) => r.with_wavelength(lambda),
+            None => r,
+        };
+        let (radiance, first_hit) = Self::ray_color(&r, self.max_depth, world);
+        let sample_color = match lambda {
+            Some(lambda) => spectrum::to_linear_srgb(radiance.x(), lambda),
+            None => radiance,
+        };
+        film.add_sample(x, y, &sample_color, self.filter.as_ref());
+
+        if let (Some(aovs), Some(rec)) = (aovs, &first_hit) {
+            aovs.add_hit(i, j, &r, rec);
+        }
+    }
 
-                let result = pixel_color.correct_color();
-                data[data_idx] = result[0];
-                data[data_idx+1] = result[1];
-                data[data_idx+2] = result[2];
-                data_idx += 3;
-            }
+    // show the film in the preview window, opening it on first use
+    pub fn display(&mut self, film: &Film) {
+        if self.window_closed { return; }
 
-            let image = ImageView::new(ImageInfo::rgb8(self.img_width as u32, self.img_height as u32), &data);
+        if self.window.is_none() {
+            self.open_window(true);
+        }
+
+        let data = film.to_rgb8(self.display_exposure(), self.tone_map);
+        let image = ImageView::new(ImageInfo::rgb8(film.width as u32, film.height as u32), &data);
+        if let Some(window) = &self.window {
             let _ = window.set_image("image-001", image);
         }
+    }
 
-        let end_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        err.write(format!{"\rDone!                 \nExecution took: {:?}\n", end_time - start_time}.as_bytes())?;
-        // let _ = self.display(&data);
+    // default_controls lets show_image handle mouse / keyboard itself
+    // (pan and zoom of the image). the interactive viewer turns them off
+    pub fn open_window(&mut self, default_controls: bool) {
+        let options = WindowOptions::default().set_default_controls(default_controls);
+        let window = create_window("image", options).unwrap();
+        self.window_events = window.event_channel().ok();
+        self.window = Some(window);
+        self.window_closed = false;
+    }
+
+    pub fn window_events(&self) -> Option<&Receiver<WindowEvent>> {
+        self.window_events.as_ref()
+    }
+
+    pub fn img_height(&self) -> i32 {
+        self.img_height
+    }
+
+    // exposure compensation plus the physical lens' exposure, in stops
+    pub fn display_exposure(&self) -> f64 {
+        self.exposure + self.lens.map_or(0.0, |lens| lens.exposure_stops())
+    }
+
+    // vertical field of view as rendered, from the lens if there is one
+    pub fn active_vfov(&self) -> f64 {
+        self.active_vfov
+    }
+
+    // deterministic primary ray through the center of pixel i, j: no jitter
+    // and no defocus. panics if the camera was never initialized (render
+    // does this)
+    pub fn ray_at_pixel(&self, i: i32, j: i32) -> Option<Ray> {
+        assert!(self.initialized, "Camera::initialize has to be called before rays are generated");
+        self.primary_ray(i, j, &Vec3::default(), false)
+    }
+
+    // what is seen through the center of pixel i, j, if anything. the hit
+    // record carries the object id and material
+    pub fn pick<H: Hittable>(&self, world: &H, i: i32, j: i32) -> Option<HitRecord> {
+        let r = self.ray_at_pixel(i, j)?;
+
+        let mut rec = HitRecord::default();
+        if !world.hit(&r, Interval::new(0.001, libs::INFINITY), &mut rec) { return None; }
+        rec.normal = rec.mat.shading_normal(&rec);
+        Some(rec)
+    }
 
-        // LOOP TO STOP WINDOW FROM CLOSING
-        loop { }
+    // derives everything rendering needs from the public settings, which
+    // are left as they are. autofocus looks into the world
+    pub fn initialize<H: Hittable>(&mut self, world: &H) {
+        self.active_focus_dist = self.focus_dist;
+        self.setup_view();
 
-        Ok(())
+        if let Some((i, j)) = self.autofocus {
+            if let Some(rec) = self.pick(world, i, j) {
+                self.active_focus_dist = (rec.p - self.center).dot(&-self.w);
+                self.setup_view();
+            }
+        }
     }
 
-    fn initialize(&mut self) {
+    fn setup_view(&mut self) {
+        self.initialized = true;
         self.img_height = (self.img_width as f64 / self.aspect_ratio) as i32;
         self.img_height = if self.img_height < 1 { 1 } else { self.img_height };
 
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+        (self.active_vfov, self.active_defocus_angle, self.active_shutter) = match &self.lens {
+            Some(lens) => (lens.vfov(self.img_width as f64 / self.img_height as f64),
+                           lens.defocus_angle(self.active_focus_dist), lens.shutter),
+            None => (self.vfov, self.defocus_angle, self.shutter),
+        };
+        // cube map faces are always square
+        if let Projection::Cube(_) = self.projection { self.img_height = self.img_width; }
 
         self.center = self.lookfrom;
         
         // viewport dimensions
-        let theta = deg_to_rad(self.vfov);
+        let vfov = if let Projection::Cube(_) = self.projection { 90.0 } else { self.active_vfov };
+        let theta = deg_to_rad(vfov);
         let h = f64::tan(theta/2.0);
-        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_height = match self.projection {
+            Projection::Orthographic(height) => height,
+            _ => 2.0 * h * self.active_focus_dist,
+        };
         let viewport_width = viewport_height * (self.img_width as f64 / self.img_height as f64);
 
         // basis vecs for camera coordinates
-        self.w = (self.lookfrom - self.lookat).unit_vector();
-        self.u = self.vup.cross(&self.w).unit_vector();
+        let (forward, up) = match self.projection {
+            Projection::Cube(face) => face.orientation(),
+            _ => (self.lookat - self.lookfrom, self.vup),
+        };
+        self.w = (-forward).unit_vector();
+        self.u = up.cross(&self.w).unit_vector();
         self.v = self.w.cross(&self.u);
 
         // vectors across horiz / vert of screen
@@ -169,57 +509,196 @@ impl Camera {
         self.pixel_delta_v = viewport_v / self.img_height as f64;
 
         // upper left pixel of view
-        let viewport_upper_left = self.center - (self.focus_dist * self.w) - (viewport_u / 2.0) - (viewport_v / 2.0);
+        // orthographic rays start on a plane through the camera center
+        let viewport_dist = if let Projection::Orthographic(_) = self.projection { 0.0 } else { self.active_focus_dist };
+        let viewport_upper_left = self.center - (viewport_dist * self.w) - (viewport_u / 2.0) - (viewport_v / 2.0);
         self.pixel00_loc = viewport_upper_left + (self.pixel_delta_u + self.pixel_delta_v)*0.5;
 
         // camera defocus disk bases
-        let defocus_radius = self.focus_dist * f64::tan(deg_to_rad(self.defocus_angle / 2.0));
+        let defocus_radius = self.active_focus_dist * f64::tan(deg_to_rad(self.active_defocus_angle / 2.0));
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
     }
 
-    // get ray originating from origin pointed at random point around i, j
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = Self::sample_square();
+    // get ray originating from origin pointed at offset from the center of pixel i, j.
+    // None if the projection doesn't cover that point (e.g. outside a fisheye circle)
+    fn get_ray(&self, i: i32, j: i32, offset: &Vec3) -> Option<Ray> {
+        self.primary_ray(i, j, offset, true)
+    }
+
+    fn primary_ray(&self, i: i32, j: i32, offset: &Vec3, sample_lens: bool) -> Option<Ray> {
         let pixel_sample = self.pixel00_loc + 
                            (self.pixel_delta_u * (i as f64 + offset.x())) +
                            (self.pixel_delta_v * (j as f64 + offset.y()));
+        // deterministic rays see the scene as it is when the shutter opens
+        let time = if sample_lens { self.time + rand_double() * self.active_shutter } else { self.time };
 
-        let ray_origin = if self.defocus_angle <= 0.0 {self.center} else {self.defocus_disk_sample()};
-        let ray_dir = pixel_sample - ray_origin;
+        match self.projection {
+            Projection::Perspective | Projection::Cube(_) => {
+                let thin_lens = sample_lens && self.active_defocus_angle > 0.0 && self.projection == Projection::Perspective;
+                let ray_origin = if thin_lens {self.defocus_disk_sample()} else {self.center};
+                let ray_dir = pixel_sample - ray_origin;
 
-        return Ray::new(ray_origin, ray_dir);
+                Some(Ray::with_time(ray_origin, ray_dir, time))
+            }
+            Projection::Orthographic(_) => Some(Ray::with_time(pixel_sample, -self.w, time)),
+            _ => {
+                let s = (i as f64 + 0.5 + offset.x()) / self.img_width as f64;
+                let t = (j as f64 + 0.5 + offset.y()) / self.img_height as f64;
+                let aspect = self.img_width as f64 / self.img_height as f64;
+
+                let d = self.projection.camera_direction(s, t, aspect)?;
+                Some(Ray::with_time(self.center, d.x()*self.u + d.y()*self.v + d.z()*self.w, time))
+            }
+        }
     }
 
     // random point in [-.5, -.5] - [.5, .5] unit square
     fn sample_square() -> Vec3 {
-        Vec3::new(rand_double() - 0.5, rand_double() + 0.5, 0.0)
+        Vec3::new(rand_double() - 0.5, rand_double() - 0.5, 0.0)
     }
 
     fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_in_unit_disk();
+        let p = if self.aperture_blades >= 3 {
+            Vec3::random_in_polygon(self.aperture_blades, deg_to_rad(self.aperture_rotation))
+        } else {
+            Vec3::random_in_unit_disk()
+        };
         return self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v);
     }
 
-    fn ray_color<H: Hittable>(r: &Ray, depth: i32, world: &H) -> Color {
+    // radiance along r, and what r hit (with the shading normal) for the
+    // aovs
+    fn ray_color<H: Hittable>(r: &Ray, depth: i32, world: &H) -> (Color, Option<HitRecord>) {
         if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0); 
+            return (Color::new(0.0, 0.0, 0.0), None);
         }
 
         let mut rec = HitRecord::default();
 
         if world.hit(r, Interval::new(0.001, libs::INFINITY), &mut rec) {
+            rec.normal = rec.mat.shading_normal(&rec);
+            let mut emitted = rec.mat.emitted(r, &rec);
+            if let Some(lambda) = r.wavelength() {
+                let e = spectrum::rgb_to_spectrum(&emitted, lambda);
+                emitted = Color::new(e, e, e);
+            }
+
             let mut scattered = Ray::default();
             let mut attenuation = Color::default();
             if rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * Self::ray_color(&scattered, depth-1, world);
+                stats::count_ray(false);
+                // a shading normal can send the ray into the surface, so
+                // leave from the side of the geometric one it points to
+                if !rec.mat.places_scattered(&rec) {
+                    let dir = scattered.direction();
+                    scattered = scattered.spawn(rec.offset_origin(&dir), dir);
+                }
+                // spectral rays carry the same value in every channel
+                if let Some(lambda) = r.wavelength() {
+                    let a = spectrum::rgb_to_spectrum(&attenuation, lambda);
+                    attenuation = Color::new(a, a, a);
+                }
+                let color = emitted + attenuation * Self::ray_color(&scattered, depth-1, world).0;
+                return (color, Some(rec));
             }
 
-            return Color::new(0.0, 0.0, 0.0);
+            return (emitted, Some(rec));
         }
 
         let unit_dir = r.direction().unit_vector();
         let a = 0.5*(unit_dir.y() + 1.0);
-        return Color::new(1.0, 1.0, 1.0)*(1.0-a) + Color::new(0.5, 0.7, 1.0)*a;
+        let sky = Color::new(1.0, 1.0, 1.0)*(1.0-a) + Color::new(0.5, 0.7, 1.0)*a;
+        if let Some(lambda) = r.wavelength() {
+            let s = spectrum::rgb_to_spectrum(&sky, lambda);
+            return (Color::new(s, s, s), None);
+        }
+        return (sky, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    // unit sphere 5 in front of a default camera
+    fn world() -> HittableList {
+        let mat = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+        HittableList::new(Rc::new(Sphere::new(&Vec3::new(0.0, 0.0, -5.0), 1.0, mat)))
+    }
+
+    // red sphere left, blue sphere right of the image center
+    fn two_spheres() -> HittableList {
+        let mut world = HittableList::default();
+        for (x, albedo) in [(-2.0, Color::new(1.0, 0.0, 0.0)), (2.0, Color::new(0.0, 0.0, 1.0))] {
+            world.add(Rc::new(Sphere::new(&Vec3::new(x, 0.0, -5.0), 1.0, Rc::new(Lambertian::new(&albedo)))));
+        }
+        world
+    }
+
+    #[test]
+    fn pick_finds_the_object_under_a_pixel() {
+        let world = two_spheres();
+        let mut cam = Camera::default();
+        cam.initialize(&world);
+
+        // the viewport is 10 wide at the spheres, so x = -2 and 2 are
+        // columns 30 and 70
+        let left = cam.pick(&world, 30, 50).unwrap();
+        let right = cam.pick(&world, 70, 50).unwrap();
+        assert_ne!(left.object_id, right.object_id);
+        assert_ne!(left.object_id, 0);
+        assert_eq!(left.mat.albedo(&left).x(), 1.0);
+        assert_eq!(right.mat.albedo(&right).z(), 1.0);
+        // the center is sqrt(29) away
+        let distance = left.t * cam.ray_at_pixel(30, 50).unwrap().direction().length();
+        assert!((distance - (f64::sqrt(29.0) - 1.0)).abs() < 0.05, "{}", distance);
+        assert!(left.normal.z() > 0.9);
+
+        assert!(cam.pick(&world, 50, 50).is_none());
+        assert!(cam.pick(&world, 30, 5).is_none());
+    }
+
+    #[test]
+    fn pixel_rays_are_deterministic() {
+        let mut cam = Camera { defocus_angle: 5.0, shutter: 1.0, ..Camera::default() };
+        cam.initialize(&world());
+        let (a, b) = (cam.ray_at_pixel(12, 34).unwrap(), cam.ray_at_pixel(12, 34).unwrap());
+        assert!((a.origin() - cam.lookfrom).near_zero());
+        assert!((a.direction() - b.direction()).near_zero());
+        assert_eq!(a.time(), b.time());
+    }
+
+    #[test]
+    #[should_panic(expected = "initialize")]
+    fn pick_needs_an_initialized_camera() {
+        Camera::default().pick(&world(), 50, 50);
+    }
+
+    #[test]
+    fn lens_settings_leave_the_public_ones_alone() {
+        let mut cam = Camera { lens: Some(PhysicalLens::default()), shutter: 0.5, ..Camera::default() };
+        cam.initialize(&world());
+
+        assert_eq!((cam.vfov, cam.defocus_angle, cam.shutter), (90.0, 0.0, 0.5));
+        assert!((cam.active_vfov() - PhysicalLens::default().vfov(1.0)).abs() < 1e-12);
+        assert_eq!(cam.active_shutter, PhysicalLens::default().shutter);
+
+        // and taking the lens away again gives the old settings back
+        cam.lens = None;
+        cam.initialize(&world());
+        assert_eq!(cam.active_vfov(), 90.0);
+    }
+
+    #[test]
+    fn autofocus_happens_in_initialize() {
+        let mut cam = Camera { autofocus: Some((50, 50)), ..Camera::default() };
+        cam.initialize(&world());
+
+        assert_eq!(cam.focus_dist, 10.0);
+        assert!((cam.active_focus_dist - 4.0).abs() < 1e-2, "{}", cam.active_focus_dist);
     }
 }
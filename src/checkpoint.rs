@@ -0,0 +1,118 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write, Error, ErrorKind};
+
+use crate::film::Film;
+use crate::libs::*;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCKPT1\n";
+
+// everything needed to pick a long render back up where it stopped
+pub struct Checkpoint {
+    // hash of the scene and camera settings the film was rendered with
+    pub scene_hash: u64,
+    pub rng_state: [u64; 4],
+    pub film: Film,
+}
+
+impl Checkpoint {
+    // snapshot the film together with the current rng state
+    pub fn save(path: &str, scene_hash: u64, film: &Film) -> Result<(), Error> {
+        // write next to the old checkpoint first so a crash mid-write
+        // never leaves a truncated file behind
+        let tmp_path = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+
+        out.write_all(CHECKPOINT_MAGIC)?;
+        out.write_all(&scene_hash.to_le_bytes())?;
+        for s in rng_state() {
+            out.write_all(&s.to_le_bytes())?;
+        }
+        film.write_to(&mut out)?;
+        out.flush()?;
+        drop(out);
+
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a checkpoint file"));
+        }
+
+        let scene_hash = read_u64(&mut input)?;
+        let mut rng_state = [0; 4];
+        for s in rng_state.iter_mut() {
+            *s = read_u64(&mut input)?;
+        }
+        let film = Film::read_from(&mut input)?;
+
+        Ok(Self { scene_hash, rng_state, film })
+    }
+
+    // refuses to hand out the film if it belongs to a different scene
+    pub fn resume(self, scene_hash: u64) -> Result<Film, Error> {
+        if self.scene_hash != scene_hash {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "checkpoint was rendered from a different scene or camera settings"));
+        }
+
+        set_rng_state(self.rng_state);
+        Ok(self.film)
+    }
+}
+
+fn read_u64(input: &mut dyn Read) -> Result<u64, Error> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::filter::BoxFilter;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("rt-{}-{}", name, std::process::id())).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn save_and_resume() {
+        let path = temp_path("checkpoint");
+        let mut film = Film::new(3, 2);
+        film.add_sample(1.5, 0.5, &Color::new(0.25, 0.5, 1.0), &BoxFilter::default());
+
+        seed_rng(99);
+        let state = rng_state();
+        let next = rand_double();
+        set_rng_state(state);
+
+        Checkpoint::save(&path, 0x1234, &film).unwrap();
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+        seed_rng(1);
+        let resumed = Checkpoint::load(&path).unwrap().resume(0x1234).unwrap();
+        assert_eq!(rand_double(), next);
+        assert_eq!((resumed.width, resumed.height), (3, 2));
+        assert_eq!(resumed.pixel(1, 0).z(), 1.0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn other_scenes_are_refused() {
+        let path = temp_path("checkpoint-refused");
+        Checkpoint::save(&path, 1, &Film::new(1, 1)).unwrap();
+        let err = Checkpoint::load(&path).unwrap().resume(2).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        fs::write(&path, b"RTFILM2\n").unwrap();
+        assert_eq!(Checkpoint::load(&path).err().unwrap().kind(), ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
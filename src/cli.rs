@@ -0,0 +1,313 @@
+use std::env;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::tonemap::ToneMap;
+use crate::projection::Projection;
+use crate::lens::PhysicalLens;
+use crate::animation::Interpolation;
+use crate::filter::{self, Filter};
+use crate::camera::Camera;
+use crate::denoise::MAX_ITERATIONS;
+
+// options render workers get from the coordinator (see distributed.rs),
+// with whether they take a value. all but --stats change the scene's
+// camera and so the rendered image
+const WORKER_FLAGS: &[(&str, bool)] = &[
+    ("--filter", true), ("--projection", true), ("--lens", true), ("--iso", true), ("--shutter", true),
+    ("--aperture-blades", true), ("--aperture-rotation", true), ("--autofocus", true), ("--spectral", false),
+    ("--stats", false),
+];
+
+// command line options. anything not given keeps the scene's own settings
+#[derive(Default)]
+pub struct Options {
+    // write the final image as a ppm
+    pub output: Option<String>,
+    // dump the accumulated film so it can be resumed / merged later
+    pub save_film: Option<String>,
+    // continue accumulating into a previously saved film
+    pub load_film: Option<String>,
+    // exposure compensation in stops
+    pub exposure: Option<f64>,
+    pub tone_map: Option<ToneMap>,
+    pub samples_per_pixel: Option<i32>,
+    // pixel reconstruction filter
+    pub filter: Option<Rc<dyn Filter>>,
+    pub progressive: bool,
+    // seconds
+    pub time_limit: Option<Duration>,
+    pub scene: Option<String>,
+    // periodically written while rendering
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Option<Duration>,
+    // continue a render from a checkpoint of the same scene
+    pub resume: Option<String>,
+    // distributed rendering, see distributed.rs
+    pub coordinator: Option<String>,
+    pub worker: Option<String>,
+    pub spawn_workers: usize,
+    pub tile_size: Option<i32>,
+    // count rays, bvh nodes and hits. --stats-json implies it
+    pub stats: bool,
+    // render statistics for the performance dashboard
+    pub stats_json: Option<String>,
+    // write first-hit aovs to <prefix>_<aov>.pfm
+    pub aov_prefix: Option<String>,
+    // filter the film with the a-trous denoiser before output
+    pub denoise: bool,
+    pub denoise_iterations: Option<i32>,
+    pub projection: Option<Projection>,
+    // physical camera
+    pub lens: Option<PhysicalLens>,
+    pub iso: Option<f64>,
+    // seconds
+    pub shutter: Option<f64>,
+    pub aperture_blades: Option<u32>,
+    // degrees
+    pub aperture_rotation: Option<f64>,
+    // pixel to focus on, x,y
+    pub autofocus: Option<(i32, i32)>,
+    // print what is seen through this pixel instead of rendering
+    pub pick: Option<(i32, i32)>,
+    // navigate the scene in the preview window
+    pub interactive: bool,
+    // render the scene's camera animation as numbered frames
+    pub animate: bool,
+    // orbit the camera once in this many seconds instead
+    pub turntable: Option<f64>,
+    // first..last, numbered from 1
+    pub frames: Option<(i32, i32)>,
+    pub fps: Option<f64>,
+    pub interpolation: Option<Interpolation>,
+    // don't render frames whose file already exists
+    pub skip_existing: bool,
+    // trace wavelengths instead of rgb
+    pub spectral: bool,
+    // the WORKER_FLAGS given, as they were on the command line
+    pub worker_args: Vec<String>,
+}
+
+impl Options {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            if let Some((_, takes_value)) = WORKER_FLAGS.iter().find(|(flag, _)| *flag == arg) {
+                opts.worker_args.push(arg.clone());
+                if let (true, Some(value)) = (takes_value, args.peek()) {
+                    opts.worker_args.push(value.clone());
+                }
+            }
+
+            match arg.as_str() {
+                "--output"      => opts.output = Some(Self::value(&arg, args.next())?),
+                "--save-film"   => opts.save_film = Some(Self::value(&arg, args.next())?),
+                "--load-film"   => opts.load_film = Some(Self::value(&arg, args.next())?),
+                "--exposure"    => opts.exposure = Some(Self::number(&arg, args.next())?),
+                "--tonemap"     => {
+                    let name = Self::value(&arg, args.next())?;
+                    opts.tone_map = Some(ToneMap::parse(&name).ok_or(format!("unknown tone mapping operator: {}", name))?);
+                }
+                "--spp"         => opts.samples_per_pixel = Some(Self::number(&arg, args.next())?),
+                "--filter"      => {
+                    let spec = Self::value(&arg, args.next())?;
+                    opts.filter = Some(filter::parse(&spec).ok_or(format!("invalid filter (box, tent, gaussian, mitchell)[:radius]: {}", spec))?);
+                }
+                "--progressive" => opts.progressive = true,
+                "--time-limit"  => opts.time_limit = Some(Self::seconds(&arg, args.next())?),
+                "--scene"       => opts.scene = Some(Self::value(&arg, args.next())?),
+                "--checkpoint"  => opts.checkpoint = Some(Self::value(&arg, args.next())?),
+                "--checkpoint-interval" => opts.checkpoint_interval = Some(Self::seconds(&arg, args.next())?),
+                "--resume"      => opts.resume = Some(Self::value(&arg, args.next())?),
+                "--coordinator" => opts.coordinator = Some(Self::value(&arg, args.next())?),
+                "--worker"      => opts.worker = Some(Self::value(&arg, args.next())?),
+                "--spawn-workers" => opts.spawn_workers = Self::number(&arg, args.next())?,
+                "--tile-size"   => opts.tile_size = Some(Self::positive(&arg, args.next())?),
+                "--stats"       => opts.stats = true,
+                "--stats-json"  => {
+                    opts.stats_json = Some(Self::value(&arg, args.next())?);
+                    opts.stats = true;
+                    // workers only need to count
+                    opts.worker_args.push("--stats".to_string());
+                }
+                "--aovs"        => opts.aov_prefix = Some(Self::value(&arg, args.next())?),
+                "--denoise"     => opts.denoise = true,
+                "--denoise-iterations" => {
+                    let iterations = Self::number(&arg, args.next())?;
+                    if !(1..=MAX_ITERATIONS).contains(&iterations) {
+                        return Err(format!("--denoise-iterations has to be in 1..={}: {}", MAX_ITERATIONS, iterations));
+                    }
+                    opts.denoise_iterations = Some(iterations);
+                }
+                "--projection"  => {
+                    let spec = Self::value(&arg, args.next())?;
+                    opts.projection = Some(Projection::parse(&spec).ok_or(format!("invalid projection: {}", spec))?);
+                }
+                "--lens"        => {
+                    let spec = Self::value(&arg, args.next())?;
+                    opts.lens = Some(PhysicalLens::parse(&spec).ok_or(format!("invalid lens (focal:sensor:f-number): {}", spec))?);
+                }
+                "--iso"         => opts.iso = Some(Self::number(&arg, args.next())?),
+                "--shutter"     => opts.shutter = Some(Self::number(&arg, args.next())?),
+                "--aperture-blades" => opts.aperture_blades = Some(Self::number(&arg, args.next())?),
+                "--aperture-rotation" => opts.aperture_rotation = Some(Self::number(&arg, args.next())?),
+                "--autofocus"   => opts.autofocus = Some(Self::pixel(&arg, args.next())?),
+                "--pick"        => opts.pick = Some(Self::pixel(&arg, args.next())?),
+                "--interactive" => opts.interactive = true,
+                "--animate"     => opts.animate = true,
+                "--turntable"   => {
+                    opts.turntable = Some(Self::number(&arg, args.next())?);
+                    opts.animate = true;
+                }
+                "--frames"      => {
+                    let spec = Self::value(&arg, args.next())?;
+                    let range = spec.split_once("..").and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)));
+                    opts.frames = Some(range.ok_or(format!("invalid frame range (first..last): {}", spec))?);
+                }
+                "--fps"         => {
+                    let fps: f64 = Self::positive(&arg, args.next())?;
+                    if fps.is_infinite() { return Err(format!("invalid value for {}: {}", arg, fps)); }
+                    opts.fps = Some(fps);
+                }
+                "--interpolation" => {
+                    let name = Self::value(&arg, args.next())?;
+                    opts.interpolation = Some(Interpolation::parse(&name).ok_or(format!("unknown interpolation (linear, spline): {}", name))?);
+                }
+                "--skip-existing" => opts.skip_existing = true,
+                "--spectral"    => opts.spectral = true,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        // workers only send back film, the aovs stay with them
+        if opts.coordinator.is_some() && (opts.denoise || opts.aov_prefix.is_some()) {
+            return Err("--denoise and --aovs don't work with --coordinator".to_string());
+        }
+
+        Ok(opts)
+    }
+
+    // applies the WORKER_FLAGS to a freshly loaded scene's camera. this has
+    // to happen before the scene is hashed
+    pub fn override_camera(&self, cam: &mut Camera) {
+        if let Some(filter) = &self.filter { cam.filter = filter.clone(); }
+        if let Some(projection) = self.projection { cam.projection = projection; }
+        if let Some(lens) = self.lens { cam.lens = Some(lens); }
+        if let Some(blades) = self.aperture_blades { cam.aperture_blades = blades; }
+        if let Some(rotation) = self.aperture_rotation { cam.aperture_rotation = rotation; }
+        if self.autofocus.is_some() { cam.autofocus = self.autofocus; }
+        if self.spectral { cam.spectral = true; }
+        match &mut cam.lens {
+            Some(lens) => {
+                if let Some(iso) = self.iso { lens.iso = iso; }
+                if let Some(shutter) = self.shutter { lens.shutter = shutter; }
+            }
+            // without a lens the shutter only controls motion blur
+            None => if let Some(shutter) = self.shutter { cam.shutter = shutter; },
+        }
+    }
+
+    fn value(flag: &str, next: Option<String>) -> Result<String, String> {
+        next.ok_or(format!("missing value for {}", flag))
+    }
+
+    fn pixel(flag: &str, next: Option<String>) -> Result<(i32, i32), String> {
+        let spec = Self::value(flag, next)?;
+        let pixel = spec.split_once(',').and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)));
+        pixel.ok_or(format!("invalid pixel for {} (x,y): {}", flag, spec))
+    }
+
+    fn number<T: std::str::FromStr>(flag: &str, next: Option<String>) -> Result<T, String> {
+        let value = Self::value(flag, next)?;
+        value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+    }
+
+    fn positive<T: std::str::FromStr + PartialOrd + Default + std::fmt::Display>(flag: &str, next: Option<String>) -> Result<T, String> {
+        let value: T = Self::number(flag, next)?;
+        // nan is not comparable and fails too
+        if value.partial_cmp(&T::default()) != Some(std::cmp::Ordering::Greater) {
+            return Err(format!("{} has to be positive: {}", flag, value));
+        }
+        Ok(value)
+    }
+
+    // negative, nan and infinite durations are errors instead of panics
+    fn seconds(flag: &str, next: Option<String>) -> Result<Duration, String> {
+        let value = Self::value(flag, next)?;
+        let seconds = value.parse().ok().and_then(|s| Duration::try_from_secs_f64(s).ok());
+        seconds.ok_or(format!("invalid duration for {}: {}", flag, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+
+    use super::*;
+    use crate::libs::Fnv1a;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse_from(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn tile_size_has_to_be_positive() {
+        assert_eq!(parse(&["--tile-size", "16"]).unwrap().tile_size, Some(16));
+        assert!(parse(&["--tile-size", "0"]).is_err());
+        assert!(parse(&["--tile-size", "-8"]).is_err());
+    }
+
+    #[test]
+    fn durations_are_checked() {
+        assert_eq!(Options::seconds("--time-limit", Some("1.5".to_string())), Ok(Duration::from_millis(1500)));
+        for seconds in ["-1", "nan", "inf", "1e300", "soon"] {
+            assert!(Options::seconds("--time-limit", Some(seconds.to_string())).is_err(), "{}", seconds);
+        }
+        assert!(parse(&["--checkpoint-interval", "-5"]).is_err());
+    }
+
+    #[test]
+    fn fps_has_to_be_positive_and_finite() {
+        assert_eq!(parse(&["--fps", "30"]).unwrap().fps, Some(30.0));
+        for fps in ["0", "-24", "nan", "inf"] {
+            assert!(parse(&["--fps", fps]).is_err(), "{}", fps);
+        }
+    }
+
+    #[test]
+    fn denoise_settings_are_checked() {
+        assert_eq!(parse(&["--denoise-iterations", "3"]).unwrap().denoise_iterations, Some(3));
+        assert!(parse(&["--denoise-iterations", "0"]).is_err());
+        assert!(parse(&["--denoise-iterations", "40"]).is_err());
+        assert!(parse(&["--coordinator", "0.0.0.0:7000", "--denoise"]).is_err());
+        assert!(parse(&["--coordinator", "0.0.0.0:7000", "--aovs", "out"]).is_err());
+    }
+
+    #[test]
+    fn scene_options_are_kept_for_workers() {
+        let opts = parse(&["--spp", "64", "--filter", "tent:1", "--spectral", "--output", "out.ppm", "--iso", "400",
+                           "--stats-json", "stats.json"]).unwrap();
+        assert_eq!(opts.worker_args, ["--filter", "tent:1", "--spectral", "--iso", "400", "--stats"]);
+
+        // a worker parsing them again ends up with the same camera
+        let mut cam = Camera::default();
+        let settings = parse(&opts.worker_args.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+        settings.override_camera(&mut cam);
+        assert!(settings.stats && settings.stats_json.is_none());
+        assert!(cam.spectral);
+        let mut expected = Camera::default();
+        opts.override_camera(&mut expected);
+        let hash = |cam: &Camera| {
+            let mut hasher = Fnv1a::default();
+            cam.hash_settings(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&cam), hash(&expected));
+    }
+}
@@ -1,26 +1,29 @@
 use std::io::Write;
 
 use crate::interval::Interval;
+use crate::tonemap::ToneMap;
 
 use super::vec3::*;
 
 pub type Color = Vec3;
 impl Color {
-    fn linear_to_gamma(linear_component: f64) -> f64 {
+    // srgb opto-electronic transfer function
+    fn linear_to_srgb(linear_component: f64) -> f64 {
         match linear_component {
-            0.0.. => f64::sqrt(linear_component),
-            _   => 0.0
+            ..=0.0 => 0.0,
+            ..=0.0031308 => 12.92 * linear_component,
+            _ => 1.055 * f64::powf(linear_component, 1.0/2.4) - 0.055,
         }
     }
 
-    pub fn correct_color(&self) -> [u8; 3] {
-        let mut r = self.x();
-        let mut g = self.y();
-        let mut b = self.z();
+    // exposure (in stops) and tone mapping followed by the srgb curve,
+    // only meant for display / 8-bit output
+    pub fn correct_color(&self, exposure: f64, tone_map: ToneMap) -> [u8; 3] {
+        let mapped = tone_map.apply(&(*self * f64::powf(2.0, exposure)));
 
-        r = Color::linear_to_gamma(r);
-        g = Color::linear_to_gamma(g);
-        b = Color::linear_to_gamma(b);
+        let r = Color::linear_to_srgb(mapped.x());
+        let g = Color::linear_to_srgb(mapped.y());
+        let b = Color::linear_to_srgb(mapped.z());
 
         let intensity = Interval::new(0.0, 0.999);
         let rbyte = (255.999 * intensity.clamp(r)) as u8;
@@ -30,19 +33,8 @@ impl Color {
         return [rbyte, gbyte, bbyte];
     }
 
-    pub fn write_color(&self, file: &mut dyn Write) {
-        let mut r = self.x();
-        let mut g = self.y();
-        let mut b = self.z();
-
-        r = Color::linear_to_gamma(r);
-        g = Color::linear_to_gamma(g);
-        b = Color::linear_to_gamma(b);
-
-        let intensity = Interval::new(0.0, 0.999);
-        let rbyte = (255.999 * intensity.clamp(r)) as i32;
-        let gbyte = (255.999 * intensity.clamp(g)) as i32;
-        let bbyte = (255.999 * intensity.clamp(b)) as i32;
+    pub fn write_color(&self, file: &mut dyn Write, exposure: f64, tone_map: ToneMap) {
+        let [rbyte, gbyte, bbyte] = self.correct_color(exposure, tone_map);
 
         let formatted = format!("{} {} {}\n", rbyte, gbyte, bbyte);
         let _ = file.write(formatted.as_bytes());
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use crate::aov::Aovs;
+use crate::color::Color;
+use crate::film::Film;
+
+// edge-avoiding a-trous wavelet filter (Dammertz et al. 2010). the noisy
+// color is divided by albedo first so textures are not blurred, filtered
+// with weights from the normal / albedo / depth buffers, then multiplied
+// back. works on the linear film, before tone mapping
+pub struct Denoiser {
+    pub iterations: i32,
+    // how fast the weights fall off for differences in each buffer
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    pub sigma_depth: f64,
+    pub sigma_albedo: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_normal: 64.0,
+            sigma_depth: 0.1,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+// the last pass reaches pixels 2^MAX_ITERATIONS away, more is pointless
+// for any image size
+pub const MAX_ITERATIONS: i32 = 12;
+
+// B3 spline
+const KERNEL: [f64; 5] = [1.0/16.0, 1.0/4.0, 3.0/8.0, 1.0/4.0, 1.0/16.0];
+
+impl Denoiser {
+    pub fn denoise(&self, film: &Film, aovs: &Aovs) -> Film {
+        let (width, height) = (film.width, film.height);
+        let eps = 1e-3;
+
+        // demodulate
+        let mut image: Vec<Color> = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let a = aovs.albedo(i, j);
+                let c = film.pixel(i, j);
+                image.push(Color::new(c.x() / f64::max(a.x(), eps),
+                                      c.y() / f64::max(a.y(), eps),
+                                      c.z() / f64::max(a.z(), eps)));
+            }
+        }
+
+        for iteration in 0..self.iterations.min(MAX_ITERATIONS) {
+            let step = 1 << iteration;
+            // finer levels hold less noise, so trust color differences more
+            let sigma_color = self.sigma_color * f64::powi(2.0, -iteration);
+            image = self.filter_pass(&image, aovs, step, sigma_color);
+        }
+
+        // remodulate
+        let mut pixels = Vec::with_capacity(image.len());
+        for j in 0..height {
+            for i in 0..width {
+                let a = aovs.albedo(i, j);
+                let c = image[(j * width + i) as usize];
+                pixels.push(Color::new(c.x() * f64::max(a.x(), eps),
+                                       c.y() * f64::max(a.y(), eps),
+                                       c.z() * f64::max(a.z(), eps)));
+            }
+        }
+
+        film.with_pixels(pixels)
+    }
+
+    fn filter_pass(&self, image: &[Color], aovs: &Aovs, step: i32, sigma_color: f64) -> Vec<Color> {
+        let (width, height) = (aovs.width, aovs.height);
+        let mut out = Vec::with_capacity(image.len());
+
+        for j in 0..height {
+            for i in 0..width {
+                let c_p = image[(j * width + i) as usize];
+                let n_p = aovs.normal(i, j);
+                let z_p = aovs.depth(i, j);
+                let a_p = aovs.albedo(i, j);
+
+                let mut sum = Color::default();
+                let mut weight_sum = 0.0;
+
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let qi = i + (dx as i32 - 2) * step;
+                        let qj = j + (dy as i32 - 2) * step;
+                        if qi < 0 || qi >= width || qj < 0 || qj >= height { continue; }
+
+                        let c_q = image[(qj * width + qi) as usize];
+                        let w_color = f64::exp(-(c_p - c_q).length_squared() / (sigma_color * sigma_color));
+                        let w_normal = f64::powf(f64::max(0.0, n_p.dot(&aovs.normal(qi, qj))), self.sigma_normal);
+                        // relative so the same sigma works near and far
+                        let w_depth = f64::exp(-f64::abs(z_p - aovs.depth(qi, qj)) / (self.sigma_depth * f64::max(z_p, 1e-3)));
+                        let w_albedo = f64::exp(-(a_p - aovs.albedo(qi, qj)).length_squared() / (self.sigma_albedo * self.sigma_albedo));
+
+                        let weight = kx * ky * w_color * w_normal * w_depth * w_albedo;
+                        sum += c_q * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                out.push(if weight_sum > 0.0 { sum / weight_sum } else { c_p });
+            }
+        }
+
+        out
+    }
+}
+
+// <stem>_noisy.<ext> next to output, where the raw render goes
+pub fn noisy_path(output: &str) -> PathBuf {
+    let output = Path::new(output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{}_noisy.{}", stem, ext.to_string_lossy()),
+        None => format!("{}_noisy", stem),
+    };
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noisy_path_keeps_directory_and_extension() {
+        assert_eq!(noisy_path("out.ppm"), Path::new("out_noisy.ppm"));
+        assert_eq!(noisy_path("../renders/out.ppm"), Path::new("../renders/out_noisy.ppm"));
+        assert_eq!(noisy_path("../out"), Path::new("../out_noisy"));
+        assert_eq!(noisy_path("dir.v2/out"), Path::new("dir.v2/out_noisy"));
+    }
+}
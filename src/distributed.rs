@@ -0,0 +1,385 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::{stderr, BufRead, BufReader, BufWriter, Write, Error, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bvh_node::BvhNode;
+use crate::camera::Camera;
+use crate::cli::Options;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::scenes::Scene;
+use crate::stats::{self, Counters};
+
+// coordinator / worker rendering over tcp. the coordinator splits the
+// image into tiles and hands them out one at a time, workers rebuild the
+// same scene by name and send back the linear film of each tile. the
+// command line options that change the scene (cli::WORKER_FLAGS) are sent
+// along and applied by the worker before it compares hashes. with --stats
+// workers report their counters once they are done.
+//
+// protocol (text lines, film data in Film::write_to format):
+//   coordinator -> worker   SCENE <hash> <spp> <count>, then the scene name
+//                           and <count> options / values, one per line so
+//                           they can contain spaces
+//   worker -> coordinator   READY | ERROR <reason>
+//   coordinator -> worker   TILE <x0> <y0> <x1> <y1> | DONE
+//   worker -> coordinator   RESULT <x0> <y0> followed by the film region
+//   worker -> coordinator   STATS <counters> after DONE, see Counters::to_fields
+
+#[derive(Clone, Copy)]
+struct Tile {
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+}
+
+// state shared between the coordinator and its connection threads
+struct Shared {
+    scene: String,
+    scene_hash: u64,
+    samples_per_pixel: i32,
+    settings: Vec<String>,
+    tiles: Mutex<VecDeque<Tile>>,
+    tile_count: usize,
+    film: Mutex<Film>,
+    tiles_done: AtomicUsize,
+    // connections accepted so far / still being served
+    connected: AtomicUsize,
+    workers: AtomicUsize,
+    stop: AtomicBool,
+    // reported by the workers, with --stats. the flag is thread local so
+    // the connection threads get a copy
+    stats: bool,
+    counters: Mutex<Counters>,
+}
+
+// settings are the options workers need to build the same camera, see
+// Options::worker_args
+#[allow(clippy::too_many_arguments)]
+pub fn coordinate<H: Hittable>(cam: &mut Camera, world: &H, scene: &str, scene_hash: u64, settings: &[String], addr: &str,
+                  tile_size: i32, spawn_workers: usize) -> Result<Film, Error> {
+    if let Some(value) = std::iter::once(scene).chain(settings.iter().map(String::as_str)).find(|s| s.contains('\n')) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("can't send a line break to workers: {:?}", value)));
+    }
+    cam.initialize(world);
+    let (width, height) = (cam.img_width, cam.img_height());
+
+    let mut tiles = VecDeque::new();
+    for y0 in (0..height).step_by(tile_size as usize) {
+        for x0 in (0..width).step_by(tile_size as usize) {
+            tiles.push_back(Tile { x0, y0, x1: i32::min(x0 + tile_size, width), y1: i32::min(y0 + tile_size, height) });
+        }
+    }
+    let tile_count = tiles.len();
+
+    let shared = Arc::new(Shared {
+        scene: scene.to_string(),
+        scene_hash,
+        samples_per_pixel: cam.samples_per_pixel,
+        settings: settings.to_vec(),
+        tiles: Mutex::new(tiles),
+        tile_count,
+        film: Mutex::new(Film::new(width, height)),
+        tiles_done: AtomicUsize::new(0),
+        connected: AtomicUsize::new(0),
+        workers: AtomicUsize::new(0),
+        stop: AtomicBool::new(false),
+        stats: stats::enabled(),
+        counters: Mutex::new(Counters::default()),
+    });
+
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?.to_string();
+    let mut err = stderr();
+    err.write_all(format!("Coordinator listening on {}\n", local_addr).as_bytes())?;
+
+    // workers on this machine, mostly for testing
+    let mut children: Vec<Child> = Vec::new();
+    for _ in 0..spawn_workers {
+        children.push(Command::new(env::current_exe()?).args(["--worker", &local_addr]).spawn()?);
+    }
+
+    let acceptor_shared = shared.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue; };
+            let shared = acceptor_shared.clone();
+            // counted here so the coordinator never sees a worker that was
+            // accepted but not yet served
+            shared.connected.fetch_add(1, Ordering::SeqCst);
+            shared.workers.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                if let Err(e) = serve_worker(stream, &shared) {
+                    let _ = stderr().write_all(format!("\nWorker {} dropped: {}\n", peer, e).as_bytes());
+                }
+                shared.workers.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    let start = Instant::now();
+    let mut failure = None;
+    while shared.tiles_done.load(Ordering::SeqCst) < tile_count {
+        thread::sleep(Duration::from_millis(250));
+
+        let remaining = tile_count - shared.tiles_done.load(Ordering::SeqCst);
+        err.write_all(format!("\rTiles remaining: {} ", remaining).as_bytes())?;
+
+        let film = shared.film.lock().unwrap().clone();
+        cam.display(&film);
+
+        if cam.should_stop(start) { break; }
+
+        // failed tiles are queued again for the workers that are left. once
+        // every worker is gone (and no spawned one can still connect)
+        // nobody will render them
+        let children_alive = children.iter_mut().any(|child| matches!(child.try_wait(), Ok(None)));
+        let had_workers = shared.connected.load(Ordering::SeqCst) > 0 || !children.is_empty();
+        if had_workers && !children_alive && shared.workers.load(Ordering::SeqCst) == 0 {
+            // the last tiles may have come in since the count above
+            let remaining = tile_count - shared.tiles_done.load(Ordering::SeqCst);
+            if remaining > 0 {
+                failure = Some(Error::other(format!("all workers are gone with {} tiles left", remaining)));
+            }
+            break;
+        }
+    }
+
+    // workers get DONE on their next request, anything still rendering
+    // after an early stop is killed
+    shared.stop.store(true, Ordering::SeqCst);
+    let finished = shared.tiles_done.load(Ordering::SeqCst) == tile_count;
+    for mut child in children {
+        if !finished { let _ = child.kill(); }
+        let _ = child.wait();
+    }
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    // the last workers may still be sending their counters
+    let wait_start = Instant::now();
+    while shared.workers.load(Ordering::SeqCst) > 0 && wait_start.elapsed() < Duration::from_secs(2) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    stats::add(&shared.counters.lock().unwrap());
+
+    err.write_all(format!("\rDone!                 \nExecution took: {:?}\n", start.elapsed()).as_bytes())?;
+
+    let film = shared.film.lock().unwrap().clone();
+    cam.display(&film);
+    Ok(film)
+}
+
+// hand tiles to one connected worker until all of them are done
+fn serve_worker(stream: TcpStream, shared: &Shared) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    write_handshake(&mut writer, &shared.scene, shared.scene_hash, shared.samples_per_pixel, &shared.settings)?;
+    writer.flush()?;
+
+    let reply = read_line(&mut reader)?;
+    if reply != "READY" {
+        return Err(Error::other(reply));
+    }
+
+    loop {
+        let Some(tile) = next_tile(shared) else {
+            writeln!(writer, "DONE")?;
+            writer.flush()?;
+            if shared.stats {
+                let counters = read_stats(&mut reader)?;
+                shared.counters.lock().unwrap().add(&counters);
+            }
+            return Ok(());
+        };
+
+        writeln!(writer, "TILE {} {} {} {}", tile.x0, tile.y0, tile.x1, tile.y1)?;
+        writer.flush()?;
+
+        match read_result(&mut reader) {
+            Ok((x0, y0, region)) => {
+                shared.film.lock().unwrap().merge_at(&region, x0, y0)?;
+                shared.tiles_done.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(e) => {
+                // give the tile to someone else
+                shared.tiles.lock().unwrap().push_back(tile);
+                return Err(e);
+            }
+        }
+    }
+}
+
+// an empty queue doesn't mean the render is finished, a tile another
+// worker is rendering comes back if that worker fails
+fn next_tile(shared: &Shared) -> Option<Tile> {
+    loop {
+        if shared.stop.load(Ordering::SeqCst) || shared.tiles_done.load(Ordering::SeqCst) == shared.tile_count {
+            return None;
+        }
+        if let Some(tile) = shared.tiles.lock().unwrap().pop_front() {
+            return Some(tile);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn read_result(reader: &mut BufReader<TcpStream>) -> Result<(i32, i32, Film), Error> {
+    let line = read_line(reader)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (x0, y0) = match fields.as_slice() {
+        ["RESULT", x0, y0] => (parse(x0)?, parse(y0)?),
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("unexpected reply: {}", line))),
+    };
+
+    Ok((x0, y0, Film::read_from(reader)?))
+}
+
+fn read_stats(reader: &mut BufReader<TcpStream>) -> Result<Counters, Error> {
+    let line = read_line(reader)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields.as_slice() {
+        ["STATS", counters @ ..] => Counters::from_fields(counters),
+        _ => None,
+    }.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unexpected reply: {}", line)))
+}
+
+// connect to a coordinator and render tiles until told to stop
+pub fn work(addr: &str) -> Result<(), Error> {
+    let stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let (name, scene_hash, spp, settings) = read_handshake(&mut reader)?;
+    let settings = match Options::parse_from(settings) {
+        Ok(settings) => settings,
+        Err(e) => return refuse(&mut writer, &e),
+    };
+
+    let mut scene = match Scene::load(&name) {
+        Ok(scene) => scene,
+        Err(e) => return refuse(&mut writer, &e.to_string()),
+    };
+    settings.override_camera(&mut scene.cam);
+    if settings.stats { stats::enable(); }
+    if scene.hash() != scene_hash {
+        return refuse(&mut writer, "scene differs from the coordinator's");
+    }
+    let Scene { world, mut cam, .. } = scene;
+    let world = HittableList::new(Rc::new(BvhNode::from_hittable_list(world)));
+
+    cam.samples_per_pixel = spp;
+    cam.initialize(&world);
+    // samples splat up to the filter radius outside their own tile
+    let pad = cam.filter.radius().ceil() as i32;
+
+    writeln!(writer, "READY")?;
+    writer.flush()?;
+
+    loop {
+        let line = read_line(&mut reader)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let tile = match fields.as_slice() {
+            ["DONE"] => break,
+            ["TILE", x0, y0, x1, y1] => Tile { x0: parse(x0)?, y0: parse(y0)?, x1: parse(x1)?, y1: parse(y1)? },
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unexpected message: {}", line))),
+        };
+
+        let mut film = Film::new(cam.img_width, cam.img_height());
+        cam.render_tile(&world, &mut film, tile.x0, tile.y0, tile.x1, tile.y1);
+
+        let (x0, y0) = (i32::max(0, tile.x0 - pad), i32::max(0, tile.y0 - pad));
+        let region = film.crop(x0, y0, tile.x1 + pad, tile.y1 + pad);
+
+        writeln!(writer, "RESULT {} {}", x0, y0)?;
+        region.write_to(&mut writer)?;
+        writer.flush()?;
+    }
+
+    if stats::enabled() {
+        writeln!(writer, "STATS {}", stats::counters().to_fields())?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn write_handshake(writer: &mut impl Write, scene: &str, scene_hash: u64, spp: i32, settings: &[String]) -> Result<(), Error> {
+    writeln!(writer, "SCENE {} {} {}", scene_hash, spp, settings.len())?;
+    writeln!(writer, "{}", scene)?;
+    for setting in settings {
+        writeln!(writer, "{}", setting)?;
+    }
+    Ok(())
+}
+
+// scene name, hash, spp and the settings as separate arguments
+fn read_handshake(reader: &mut impl BufRead) -> Result<(String, u64, i32, Vec<String>), Error> {
+    let line = read_line(reader)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (scene_hash, spp, count) = match fields.as_slice() {
+        ["SCENE", hash, spp, count] => (parse::<u64>(hash)?, parse::<i32>(spp)?, parse::<usize>(count)?),
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("unexpected message: {}", line))),
+    };
+    let name = read_value(reader)?;
+    let settings = (0..count).map(|_| read_value(reader)).collect::<Result<_, _>>()?;
+    Ok((name, scene_hash, spp, settings))
+}
+
+fn refuse(writer: &mut BufWriter<TcpStream>, reason: &str) -> Result<(), Error> {
+    writeln!(writer, "ERROR {}", reason)?;
+    writer.flush()?;
+    Err(Error::new(ErrorKind::InvalidInput, reason.to_string()))
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+// a whole line as one value, surrounding spaces included
+fn read_value(reader: &mut impl BufRead) -> Result<String, Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    Ok(line.strip_suffix('\r').unwrap_or(line).to_string())
+}
+
+fn parse<T: std::str::FromStr>(field: &str) -> Result<T, Error> {
+    field.parse().map_err(|_| Error::new(ErrorKind::InvalidData, format!("invalid number: {}", field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_keeps_spaces() {
+        let settings = ["--filter".to_string(), " gaussian 1.5".to_string()];
+        let mut data = Vec::new();
+        write_handshake(&mut data, "model:my models/teapot.obj", 42, 16, &settings).unwrap();
+
+        let (name, scene_hash, spp, read) = read_handshake(&mut data.as_slice()).unwrap();
+        assert_eq!(name, "model:my models/teapot.obj");
+        assert_eq!((scene_hash, spp), (42, 16));
+        assert_eq!(read, settings);
+        // a setting missing
+        assert!(read_handshake(&mut &data[..data.len() - " gaussian 1.5\n".len()]).is_err());
+    }
+}
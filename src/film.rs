@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write, Error, ErrorKind};
+
+use crate::color::Color;
+use crate::filter::Filter;
+use crate::tonemap::ToneMap;
+
+const FILM_MAGIC: &[u8; 8] = b"RTFILM2\n";
+
+// filter weight sums below this are treated as no coverage. negative filter
+// lobes can cancel a pixel's weights out and dividing by what is left
+// blows the pixel up
+const MIN_WEIGHT: f64 = 1e-3;
+
+// accumulates linear radiance for every pixel. the displayed / written
+// image is always derived from this, never stored directly
+#[derive(Clone)]
+pub struct Film {
+    pub width: i32,
+    pub height: i32,
+    // weighted sum of linear rgb samples
+    sums: Vec<Color>,
+    // sum of filter weights
+    weights: Vec<f64>,
+    // number of samples taken inside each pixel
+    samples: Vec<u32>,
+    // plain sum of the samples taken inside each pixel, what the pixel
+    // falls back to when its filter weights don't add up to anything
+    box_sums: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            width, height,
+            sums: vec![Color::default(); pixel_count],
+            weights: vec![0.0; pixel_count],
+            samples: vec![0; pixel_count],
+            box_sums: vec![Color::default(); pixel_count],
+        }
+    }
+
+    fn index(&self, i: i32, j: i32) -> usize {
+        (j * self.width + i) as usize
+    }
+
+    // add a sample at continuous film position (x, y) to every pixel
+    // whose center lies within the filter radius
+    pub fn add_sample(&mut self, x: f64, y: f64, color: &Color, filter: &dyn Filter) {
+        let radius = filter.radius();
+        let x0 = i32::max(0, (x - radius - 0.5).ceil() as i32);
+        let x1 = i32::min(self.width - 1, (x + radius - 0.5).floor() as i32);
+        let y0 = i32::max(0, (y - radius - 0.5).ceil() as i32);
+        let y1 = i32::min(self.height - 1, (y + radius - 0.5).floor() as i32);
+
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                if weight == 0.0 { continue; }
+
+                let idx = self.index(px, py);
+                self.sums[idx] += *color * weight;
+                self.weights[idx] += weight;
+            }
+        }
+
+        let (i, j) = (x.floor() as i32, y.floor() as i32);
+        if 0 <= i && i < self.width && 0 <= j && j < self.height {
+            let idx = self.index(i, j);
+            self.samples[idx] += 1;
+            self.box_sums[idx] += *color;
+        }
+    }
+
+    // filtered linear color of pixel i, j. negative lobes can also push
+    // single channels below zero, which is clipped
+    pub fn pixel(&self, i: i32, j: i32) -> Color {
+        let idx = self.index(i, j);
+        let color = if self.weights[idx] > MIN_WEIGHT {
+            self.sums[idx] / self.weights[idx]
+        } else if self.samples[idx] > 0 {
+            self.box_sums[idx] / self.samples[idx] as f64
+        } else {
+            return Color::default();
+        };
+        Color::new(f64::max(0.0, color.x()), f64::max(0.0, color.y()), f64::max(0.0, color.z()))
+    }
+
+    // average samples per pixel over the whole film
+    pub fn effective_spp(&self) -> f64 {
+        let total: u64 = self.samples.iter().map(|&s| s as u64).sum();
+        total as f64 / self.samples.len() as f64
+    }
+
+    // 8-bit preview of the current state of the film
+    pub fn to_rgb8(&self, exposure: f64, tone_map: ToneMap) -> Vec<u8> {
+        let mut data = Vec::with_capacity(3 * self.sums.len());
+        for j in 0..self.height {
+            for i in 0..self.width {
+                data.extend_from_slice(&self.pixel(i, j).correct_color(exposure, tone_map));
+            }
+        }
+        data
+    }
+
+    // film with the same sample counts whose filtered colors are replaced,
+    // e.g. by the denoiser
+    pub fn with_pixels(&self, pixels: Vec<Color>) -> Film {
+        let mut film = self.clone();
+        film.weights = vec![1.0; pixels.len()];
+        film.box_sums = pixels.iter().zip(&self.samples).map(|(c, &n)| *c * n as f64).collect();
+        film.sums = pixels;
+        film
+    }
+
+    // copy of the region [x0, x1) x [y0, y1), clipped to the film
+    pub fn crop(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Film {
+        let (x0, y0) = (i32::max(0, x0), i32::max(0, y0));
+        let (x1, y1) = (i32::min(self.width, x1), i32::min(self.height, y1));
+
+        let mut region = Film::new(x1 - x0, y1 - y0);
+        for j in y0..y1 {
+            for i in x0..x1 {
+                let (src, dst) = (self.index(i, j), region.index(i - x0, j - y0));
+                region.sums[dst] = self.sums[src];
+                region.weights[dst] = self.weights[src];
+                region.samples[dst] = self.samples[src];
+                region.box_sums[dst] = self.box_sums[src];
+            }
+        }
+        region
+    }
+
+    // like merge, but for a smaller film whose upper left pixel lands on x0, y0
+    pub fn merge_at(&mut self, region: &Film, x0: i32, y0: i32) -> Result<(), Error> {
+        if x0 < 0 || y0 < 0 || x0 + region.width > self.width || y0 + region.height > self.height {
+            return Err(Error::new(ErrorKind::InvalidInput, "region does not fit inside the film"));
+        }
+
+        for j in 0..region.height {
+            for i in 0..region.width {
+                let (src, dst) = (region.index(i, j), self.index(x0 + i, y0 + j));
+                self.sums[dst] += region.sums[src];
+                self.weights[dst] += region.weights[src];
+                self.samples[dst] += region.samples[src];
+                self.box_sums[dst] += region.box_sums[src];
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_ppm(&self, file: &mut dyn Write, exposure: f64, tone_map: ToneMap) -> Result<(), Error> {
+        file.write_all(format!("P3\n# effective spp: {:.2}\n{} {}\n255\n",
+                               self.effective_spp(), self.width, self.height).as_bytes())?;
+        for j in 0..self.height {
+            for i in 0..self.width {
+                self.pixel(i, j).write_color(file, exposure, tone_map);
+            }
+        }
+        Ok(())
+    }
+
+    // raw dump of the accumulation buffers so a render can be resumed
+    // or merged later
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let mut input = BufReader::new(File::open(path)?);
+        Self::read_from(&mut input)
+    }
+
+    pub fn write_to(&self, out: &mut dyn Write) -> Result<(), Error> {
+        out.write_all(FILM_MAGIC)?;
+        out.write_all(&self.width.to_le_bytes())?;
+        out.write_all(&self.height.to_le_bytes())?;
+
+        for idx in 0..self.sums.len() {
+            let (sum, box_sum) = (self.sums[idx], self.box_sums[idx]);
+            for c in [sum.x(), sum.y(), sum.z(), self.weights[idx], box_sum.x(), box_sum.y(), box_sum.z()] {
+                out.write_all(&c.to_le_bytes())?;
+            }
+            out.write_all(&self.samples[idx].to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(input: &mut dyn Read) -> Result<Self, Error> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != FILM_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a film file"));
+        }
+
+        // the header is untrusted, a bogus size must not overflow below
+        let width = read_i32(input)?;
+        let height = read_i32(input)?;
+        if width <= 0 || height <= 0 || width.checked_mul(height).is_none() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid film dimensions {}x{}", width, height)));
+        }
+
+        let mut film = Self::new(width, height);
+        for idx in 0..film.sums.len() {
+            let (r, g, b) = (read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.sums[idx] = Color::new(r, g, b);
+            film.weights[idx] = read_f64(input)?;
+            let (r, g, b) = (read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.box_sums[idx] = Color::new(r, g, b);
+            film.samples[idx] = read_u32(input)?;
+        }
+        Ok(film)
+    }
+}
+
+fn read_i32(input: &mut dyn Read) -> Result<i32, Error> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u32(input: &mut dyn Read) -> Result<u32, Error> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f64(input: &mut dyn Read) -> Result<f64, Error> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{BoxFilter, MitchellFilter};
+
+    fn header(width: i32, height: i32) -> Vec<u8> {
+        let mut data = FILM_MAGIC.to_vec();
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        let mitchell = MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0);
+        let mut film = Film::new(5, 3);
+        for (k, (x, y)) in [(0.3, 0.7), (2.5, 1.5), (4.9, 2.1), (1.2, 0.2)].into_iter().enumerate() {
+            film.add_sample(x, y, &Color::new(k as f64, 0.5, -0.25), &mitchell);
+        }
+
+        let mut data = Vec::new();
+        film.write_to(&mut data).unwrap();
+        let read = Film::read_from(&mut data.as_slice()).unwrap();
+
+        assert_eq!((read.width, read.height), (5, 3));
+        assert_eq!(read.weights, film.weights);
+        assert_eq!(read.samples, film.samples);
+        for idx in 0..film.sums.len() {
+            assert_eq!(read.sums[idx].x().to_bits(), film.sums[idx].x().to_bits());
+            assert_eq!(read.box_sums[idx].z().to_bits(), film.box_sums[idx].z().to_bits());
+        }
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        for (width, height) in [(0, 4), (4, -1), (-2, -2), (65536, 65536), (i32::MAX, 2)] {
+            let err = Film::read_from(&mut header(width, height).as_slice()).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{}x{}", width, height);
+        }
+        // valid header, truncated pixels
+        assert!(Film::read_from(&mut header(2, 2).as_slice()).is_err());
+        assert!(Film::read_from(&mut b"RTFILM0\n".as_slice()).is_err());
+    }
+
+    #[test]
+    fn box_filter_averages_samples() {
+        let mut film = Film::new(2, 1);
+        let filter = BoxFilter::default();
+        film.add_sample(0.25, 0.5, &Color::new(1.0, 0.0, 0.0), &filter);
+        film.add_sample(0.75, 0.5, &Color::new(0.0, 1.0, 0.0), &filter);
+        film.add_sample(1.5, 0.5, &Color::new(0.0, 0.0, 1.0), &filter);
+
+        let p = film.pixel(0, 0);
+        assert!((p.x() - 0.5).abs() < 1e-12 && (p.y() - 0.5).abs() < 1e-12 && p.z() == 0.0);
+        assert_eq!(film.pixel(1, 0).z(), 1.0);
+        assert_eq!(film.effective_spp(), 1.5);
+    }
+
+    #[test]
+    fn cancelled_weights_fall_back_to_the_box_estimate() {
+        let mitchell = MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0);
+        let mut film = Film::new(4, 1);
+
+        // a sample in the negative lobe of pixel 0 and one right at the
+        // edge of pixel 3 make pixel 0's weight negative
+        film.add_sample(1.95, 0.5, &Color::new(1.0, 1.0, 1.0), &mitchell);
+        assert!(film.weights[0] < 0.0);
+        let p = film.pixel(0, 0);
+        assert_eq!((p.x(), p.y(), p.z()), (0.0, 0.0, 0.0));
+
+        // weights that nearly cancel use the samples inside the pixel
+        film.add_sample(0.9, 0.5, &Color::new(0.2, 0.4, 0.6), &mitchell);
+        let idx = film.index(0, 0);
+        film.weights[idx] = 1e-9;
+        let p = film.pixel(0, 0);
+        assert!((p.x() - 0.2).abs() < 1e-12 && (p.y() - 0.4).abs() < 1e-12 && (p.z() - 0.6).abs() < 1e-12);
+
+        for i in 0..4 {
+            let p = film.pixel(i, 0);
+            assert!(p.x().is_finite() && p.x() >= 0.0 && p.z() >= 0.0);
+        }
+    }
+}
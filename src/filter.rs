@@ -0,0 +1,210 @@
+use std::hash::Hasher;
+use std::rc::Rc;
+
+// reconstruction filters used to weight a sample's contribution to the
+// pixels around it. x and y are offsets from the pixel center in pixels
+pub trait Filter {
+    fn radius(&self) -> f64;
+
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+
+    // the filter's kind and parameters, for scene hashes
+    fn hash_into(&self, state: &mut dyn Hasher);
+}
+
+// box[:radius] | tent[:radius] | gaussian[:radius[:alpha]] |
+// mitchell[:radius[:b:c]]
+pub fn parse(spec: &str) -> Option<Rc<dyn Filter>> {
+    let mut parts = spec.split(':');
+    let name = parts.next()?;
+    let params: Vec<f64> = parts.map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    if params.first().is_some_and(|&r| r <= 0.0 || !r.is_finite()) { return None; }
+
+    let filter: Rc<dyn Filter> = match (name, params.as_slice()) {
+        ("box", [])                  => Rc::new(BoxFilter::default()),
+        ("box", [r])                 => Rc::new(BoxFilter::new(*r)),
+        ("tent", [])                 => Rc::new(TentFilter::new(1.0)),
+        ("tent", [r])                => Rc::new(TentFilter::new(*r)),
+        ("gaussian", [])             => Rc::new(GaussianFilter::new(1.5, 2.0)),
+        ("gaussian", [r])            => Rc::new(GaussianFilter::new(*r, 2.0)),
+        ("gaussian", [r, alpha])     => Rc::new(GaussianFilter::new(*r, *alpha)),
+        ("mitchell", [])             => Rc::new(MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0)),
+        ("mitchell", [r])            => Rc::new(MitchellFilter::new(*r, 1.0 / 3.0, 1.0 / 3.0)),
+        ("mitchell", [r, b, c])      => Rc::new(MitchellFilter::new(*r, *b, *c)),
+        _ => return None,
+    };
+    Some(filter)
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        // only covers the pixel the sample landed in
+        Self::new(0.5)
+    }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+pub struct GaussianFilter {
+    radius: f64,
+    alpha: f64,
+    // value at the radius, subtracted so the filter falls to 0 at its edge
+    exp_r: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, alpha: f64) -> Self {
+        Self { radius, alpha, exp_r: f64::exp(-alpha * radius * radius) }
+    }
+
+    fn gaussian(&self, d: f64) -> f64 {
+        f64::max(0.0, f64::exp(-self.alpha * d * d) - self.exp_r)
+    }
+}
+
+// cubic filter, B = C = 1/3 is the recommended default
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        Self { radius, b, c }
+    }
+
+    // x in [-1, 1] (filter support scaled down to unit radius)
+    fn mitchell_1d(&self, x: f64) -> f64 {
+        let x = f64::abs(2.0 * x);
+        let (b, c) = (self.b, self.c);
+
+        if x > 1.0 {
+            ((-b - 6.0*c) * x*x*x + (6.0*b + 30.0*c) * x*x +
+             (-12.0*b - 48.0*c) * x + (8.0*b + 24.0*c)) / 6.0
+        } else {
+            ((12.0 - 9.0*b - 6.0*c) * x*x*x + (-18.0 + 12.0*b + 6.0*c) * x*x +
+             (6.0 - 2.0*b)) / 6.0
+        }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if f64::abs(x) <= self.radius && f64::abs(y) <= self.radius { 1.0 } else { 0.0 }
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"box");
+        state.write_u64(self.radius.to_bits());
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        f64::max(0.0, self.radius - f64::abs(x)) * f64::max(0.0, self.radius - f64::abs(y))
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"tent");
+        state.write_u64(self.radius.to_bits());
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"gaussian");
+        state.write_u64(self.radius.to_bits());
+        state.write_u64(self.alpha.to_bits());
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if f64::abs(x) > self.radius || f64::abs(y) > self.radius {
+            return 0.0;
+        }
+        self.mitchell_1d(x / self.radius) * self.mitchell_1d(y / self.radius)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"mitchell");
+        for x in [self.radius, self.b, self.c] {
+            state.write_u64(x.to_bits());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_names_and_radii() {
+        assert_eq!(parse("box").unwrap().radius(), 0.5);
+        assert_eq!(parse("tent:2").unwrap().radius(), 2.0);
+        assert_eq!(parse("gaussian:1.5:4").unwrap().radius(), 1.5);
+        assert_eq!(parse("mitchell").unwrap().radius(), 2.0);
+        assert!(parse("mitchell:2:0.5").is_none());
+        assert!(parse("tent:0").is_none());
+        assert!(parse("tent:-1").is_none());
+        assert!(parse("lanczos").is_none());
+    }
+
+    #[test]
+    fn filters_vanish_at_their_radius() {
+        for spec in ["box", "tent:1.5", "gaussian:2", "mitchell:2"] {
+            let filter = parse(spec).unwrap();
+            let r = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", spec);
+            assert!(filter.evaluate(r + 1e-6, 0.0).abs() < 1e-9, "{}", spec);
+            assert!(filter.evaluate(0.0, -r - 1e-6).abs() < 1e-9, "{}", spec);
+            assert_eq!(filter.evaluate(0.3, -0.2), filter.evaluate(-0.3, 0.2), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn mitchell_has_negative_lobes() {
+        let filter = MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+        assert!(filter.evaluate(0.0, 0.0) > filter.evaluate(0.5, 0.0));
+    }
+}
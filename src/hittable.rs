@@ -1,3 +1,4 @@
+use std::hash::Hasher;
 use std::rc::*;
 
 use crate::Ray;
@@ -10,10 +11,22 @@ use crate::aabb::*;
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Vec3,
+    // shading normal, may be perturbed by the material (see
+    // Material::shading_normal). both normals face against the ray
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
+    // partial derivatives of p along u and v, the tangent frame for
+    // normal / bump maps and anisotropy
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub mat: Rc<dyn Material>,
     pub t: f64,
+    // surface coordinates for texture lookups
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    // id of the primitive that was hit, see libs::next_object_id
+    pub object_id: u32,
 }
 
 impl Default for HitRecord {
@@ -21,9 +34,15 @@ impl Default for HitRecord {
         Self {
             p: Vec3::default(),
             normal: Vec3::new(1.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(1.0, 0.0, 0.0),
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
             mat: Rc::new(Lambertian::new(&Color::new(0.0, 0.0, 0.0))),
             t: f64::default(),
+            u: f64::default(),
+            v: f64::default(),
             front_face: bool::default(),
+            object_id: u32::default(),
         }
     }
 }
@@ -33,11 +52,47 @@ impl HitRecord {
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face { *outward_normal } else { -*outward_normal };
+        self.geometric_normal = self.normal;
+    }
+
+    // start of a ray leaving the surface in direction dir, nudged off the
+    // geometric surface on dir's side. the nudge grows with the distance
+    // from the origin, as the rounding error in p does
+    pub fn offset_origin(&self, dir: &Vec3) -> Vec3 {
+        let magnitude = f64::max(self.p.x().abs(), f64::max(self.p.y().abs(), self.p.z().abs()));
+        let offset = self.geometric_normal * (1e-4 * f64::max(1.0, magnitude));
+        if dir.dot(&self.geometric_normal) > 0.0 { self.p + offset } else { self.p - offset }
     }
 }
 
 pub trait Hittable {
+    // rec is only written when returning true, so a rejected candidate (a
+    // miss, or a cutout hole) leaves the closest hit so far untouched and
+    // lists / bvh nodes simply carry on with the remaining objects
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
 
     fn bounding_box(&self) -> &AABB;
+
+    // feeds everything that changes the rendered image into state, starting
+    // with a tag for the type. Scene::hash compares worlds this way
+    fn hash_into(&self, state: &mut dyn Hasher);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_grows_with_distance_from_the_origin() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let rec = |p: Vec3| HitRecord { p, normal: up, geometric_normal: up, ..HitRecord::default() };
+
+        let near = rec(Vec3::new(0.5, 0.0, 0.0));
+        assert!((near.offset_origin(&up).y() - 1e-4).abs() < 1e-12);
+        assert!((near.offset_origin(&-up).y() + 1e-4).abs() < 1e-12);
+
+        // 1e-4 would be lost in the rounding of p out here
+        let far = rec(Vec3::new(1e9, 0.0, 0.0));
+        assert!(far.offset_origin(&up).y() >= 1e5);
+    }
 }
@@ -1,3 +1,4 @@
+use std::hash::Hasher;
 use std::rc::Rc;
 
 use crate::hittable::*;
@@ -15,10 +16,6 @@ impl HittableList {
         Self { objects: vec![object], bbox: AABB::default() }
     }
 
-    pub fn clear(&mut self) {
-        self.objects.clear();
-    }
-
     pub fn add(&mut self, object: Rc<dyn Hittable>) {
         self.bbox = AABB::from_boxes(&self.bbox, object.bounding_box());
         self.objects.push(object);
@@ -45,4 +42,12 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"list");
+        state.write_usize(self.objects.len());
+        for object in &self.objects {
+            object.hash_into(state);
+        }
+    }
 }
@@ -46,15 +46,11 @@ impl Interval {
 
     pub fn expand(&mut self, delta: f64) {
         let padding = delta/2.0;
-        self.min = self.min-padding;
-        self.max = self.max+padding;
+        self.min -= padding;
+        self.max += padding;
     }
 
     pub const fn EMPTY() -> Self {
         Interval { min: f64::INFINITY, max: f64::NEG_INFINITY }
     }
-
-    pub const fn UNIVERSE() -> Self {
-        Interval { min: f64::NEG_INFINITY, max: f64::INFINITY }
-    }
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+// physical camera parametrization. scene units are taken to be meters
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalLens {
+    // mm
+    pub focal_length: f64,
+    // mm, 36 is full frame
+    pub sensor_width: f64,
+    pub f_number: f64,
+    pub iso: f64,
+    // seconds
+    pub shutter: f64,
+}
+
+impl Default for PhysicalLens {
+    fn default() -> Self {
+        Self { focal_length: 50.0, sensor_width: 36.0, f_number: 16.0, iso: 100.0, shutter: 0.01 }
+    }
+}
+
+impl PhysicalLens {
+    // <focal length>:<sensor width>:<f-number>, e.g. 50:36:2.8
+    pub fn parse(spec: &str) -> Option<Self> {
+        let parts: Vec<f64> = spec.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        match parts.as_slice() {
+            [focal_length, sensor_width, f_number] => Some(Self {
+                focal_length: *focal_length,
+                sensor_width: *sensor_width,
+                f_number: *f_number,
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+
+    // vertical field of view in degrees for an image of the given aspect ratio
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * f64::atan(sensor_height / (2.0 * self.focal_length)).to_degrees()
+    }
+
+    // radius of the entrance pupil in meters
+    pub fn aperture_radius(&self) -> f64 {
+        (self.focal_length / self.f_number) / 2.0 / 1000.0
+    }
+
+    // defocus cone angle (degrees) that gives the same blur when focused at focus_dist
+    pub fn defocus_angle(&self, focus_dist: f64) -> f64 {
+        2.0 * f64::atan(self.aperture_radius() / focus_dist).to_degrees()
+    }
+
+    // exposure in stops relative to the "sunny 16" setting (f/16, 1/100s,
+    // ISO 100), which maps a radiance of 1 to white
+    pub fn exposure_stops(&self) -> f64 {
+        let sunny_16 = 0.01 / (16.0 * 16.0);
+        let exposure = self.shutter * (self.iso / 100.0) / (self.f_number * self.f_number);
+        f64::log2(exposure / sunny_16)
+    }
+}
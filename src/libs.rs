@@ -1,18 +1,193 @@
+use std::cell::Cell;
+use std::hash::Hasher;
+
 pub const INFINITY: f64 = f64::INFINITY;
-pub const PI: f64 = 3.1415926535897932385;
+pub const PI: f64 = std::f64::consts::PI;
 
 pub fn deg_to_rad(degrees: f64) -> f64 {
     return degrees * PI / 180.0;
 }
 
+// 64-bit fnv-1a. std's DefaultHasher may change between releases, this one
+// gives the same hash for the same bytes everywhere, so it can identify
+// scenes in checkpoints and between render workers. integers are hashed
+// little endian
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i]);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+// ids handed out to primitives in construction order. Scene::load starts
+// over from 1 for every build, so the same scene gets the same ids however
+// often it is rebuilt in one process (viewer, animation, workers)
+thread_local! {
+    static NEXT_OBJECT_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+pub fn next_object_id() -> u32 {
+    NEXT_OBJECT_ID.with(|next| next.replace(next.get() + 1))
+}
+
+pub fn reset_object_ids() {
+    NEXT_OBJECT_ID.with(|next| next.set(1));
+}
+
+// xoshiro256** generator. kept here instead of rand's thread rng so its
+// state can be saved to and restored from checkpoints
+thread_local! {
+    static RNG_STATE: Cell<[u64; 4]> = Cell::new(seed_state(rand::random()));
+}
+
+// splitmix64 expansion of a single seed into a full generator state
+fn seed_state(mut seed: u64) -> [u64; 4] {
+    let mut state = [0; 4];
+    for s in state.iter_mut() {
+        seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        *s = z ^ (z >> 31);
+    }
+    state
+}
+
+fn next_u64() -> u64 {
+    RNG_STATE.with(|cell| {
+        let mut s = cell.get();
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        cell.set(s);
+        result
+    })
+}
+
+pub fn seed_rng(seed: u64) {
+    RNG_STATE.with(|cell| cell.set(seed_state(seed)));
+}
+
+pub fn rng_state() -> [u64; 4] {
+    RNG_STATE.with(|cell| cell.get())
+}
+
+pub fn set_rng_state(state: [u64; 4]) {
+    RNG_STATE.with(|cell| cell.set(state));
+}
+
 pub fn rand_double() -> f64 {
-    rand::random_range(0.0..1.0)
+    // top 53 bits -> [0, 1)
+    (next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
 }
 
 pub fn rand_range(min: f64, max: f64) -> f64 {
-    rand::random_range(min..max)
+    min + (max - min) * rand_double()
 }
 
 pub fn rand_int(min: i32, max: i32) -> i32 {
-    rand::random_range(min..=max)
+    let span = (max as i64 - min as i64 + 1) as f64;
+    i64::min(max as i64, min as i64 + (rand_double() * span) as i64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_reference_values() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+
+        // integers are hashed as their little endian bytes on every platform
+        let mut hasher = Fnv1a::default();
+        hasher.write_u32(0x64636261);
+        assert_eq!(hasher.finish(), hash(b"abcd"));
+    }
+
+    #[test]
+    fn seeded_rng_repeats() {
+        seed_rng(42);
+        let first: Vec<u64> = (0..8).map(|_| next_u64()).collect();
+        seed_rng(42);
+        let second: Vec<u64> = (0..8).map(|_| next_u64()).collect();
+        assert_eq!(first, second);
+
+        seed_rng(43);
+        assert_ne!(next_u64(), first[0]);
+    }
+
+    #[test]
+    fn rng_state_round_trip() {
+        seed_rng(7);
+        rand_double();
+        let state = rng_state();
+        let expected: Vec<f64> = (0..4).map(|_| rand_double()).collect();
+
+        seed_rng(8);
+        set_rng_state(state);
+        let resumed: Vec<f64> = (0..4).map(|_| rand_double()).collect();
+        assert_eq!(expected, resumed);
+    }
+
+    #[test]
+    fn rng_ranges() {
+        seed_rng(1);
+        let mut seen = [false; 4];
+        for _ in 0..10000 {
+            let x = rand_double();
+            assert!((0.0..1.0).contains(&x));
+            let y = rand_range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&y));
+            let k = rand_int(0, 3);
+            assert!((0..=3).contains(&k));
+            seen[k as usize] = true;
+        }
+        assert_eq!(seen, [true; 4]);
+        // the full range must not overflow
+        let wide: Vec<i32> = (0..1000).map(|_| rand_int(i32::MIN, i32::MAX)).collect();
+        assert!(wide.iter().any(|&k| k < 0) && wide.iter().any(|&k| k > 0));
+    }
 }
@@ -11,149 +11,183 @@ mod material;
 mod aabb;
 mod bvh_node;
 mod quad;
-
-use std::io::{stderr, Write};
+mod filter;
+mod film;
+mod cli;
+mod tonemap;
+mod scenes;
+mod checkpoint;
+mod distributed;
+mod stats;
+mod aov;
+mod denoise;
+mod projection;
+mod lens;
+mod viewer;
+mod animation;
+mod animated;
+mod onb;
+mod microfacet;
+mod texture;
+mod mtl;
+mod spectrum;
+mod normal_map;
+mod subsurface;
+mod thin_film;
+mod mesh;
+mod obj;
+
+use std::io::{stderr, BufWriter};
+use std::fs::File;
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::env;
+use std::time::Instant;
 
 use vec3::Vec3;
-use color::*;
 use ray::Ray;
 use hittable::*;
-use sphere::Sphere;
 use hittable_list::HittableList;
-use libs::*;
 use interval::Interval;
-use camera::Camera;
-use material::*;
 use bvh_node::BvhNode;
-use quad::Quad;
+use film::Film;
+use cli::Options;
+use scenes::Scene;
+use checkpoint::Checkpoint;
+use stats::Report;
+use denoise::Denoiser;
+use animation::CameraAnimation;
 
 #[show_image::main]
 fn main() -> Result<(), std::io::Error> {
+    let opts = Options::parse().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if opts.stats { stats::enable(); }
+    camera::install_interrupt_handler().map_err(std::io::Error::other)?;
+
+    if let Some(addr) = &opts.worker {
+        return distributed::work(addr);
+    }
+
+    let mut report = Report::default();
+
     // world
-    let mut world = HittableList::default();
-
-    /*
-    let material_ground = Rc::new(Lambertian::new(&Color::new(1.0, 0.0, 1.0)));
-    let material_left =   Rc::new(Dielectric::new(1.51));
-    let material_center = Rc::new(Dielectric::new(1.51));
-    let material_right =  Rc::new(Dielectric::new(1.51));
-
-    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
-    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 0.0, -1.2), 0.5, material_center)));
-    world.add(Rc::new(Sphere::new(&Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
-    world.add(Rc::new(Sphere::new(&Vec3::new(1.0, 0.0, -1.0), 0.5, material_right)));
-    */
-
-    /*
-    let ground_mat = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
-    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat)));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let mat = rand_double();
-            let center = Vec3::new(a as f64 + 0.9*rand_double(), 0.2, b as f64 + 0.9*rand_double());
-            
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Rc<dyn Material>;
-
-                if mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
-                    sphere_material = Rc::new(Lambertian::new(&albedo));
-                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
-                } else if mat < 0.95 {
-                    let albedo = Color::random_bounded(0.5, 1.0);
-                    let fuzz = rand_range(0.0, 0.5);
-                    sphere_material = Rc::new(Metal::new(&albedo, fuzz));
-                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
-                } else {
-                    sphere_material = Rc::new(Dielectric::new(1.333, 0.0));
-                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
-                }
-            }
+    let phase_start = Instant::now();
+    let scene_name = opts.scene.as_deref().unwrap_or("quads");
+    let mut scene = Scene::load(scene_name)?;
+    report.phase("scene load", phase_start.elapsed());
+
+    opts.override_camera(&mut scene.cam);
+    let scene_hash = scene.hash();
+    let Scene { mut world, mut cam, animation, .. } = scene;
+
+    if let Some(exposure) = opts.exposure { cam.exposure = exposure; }
+    if let Some(tone_map) = opts.tone_map { cam.tone_map = tone_map; }
+    if let Some(spp) = opts.samples_per_pixel { cam.samples_per_pixel = spp; }
+    // a time limit stops between passes, so every pixel has the same spp
+    cam.progressive = opts.progressive || opts.resume.is_some() || opts.time_limit.is_some();
+    cam.time_limit = opts.time_limit;
+    cam.checkpoint = opts.checkpoint.clone();
+    if let Some(interval) = opts.checkpoint_interval { cam.checkpoint_interval = interval; }
+    cam.scene_hash = scene_hash;
+    // the denoiser is guided by the aov buffers
+    cam.record_aovs = opts.aov_prefix.is_some() || opts.denoise;
+
+    let phase_start = Instant::now();
+    world = HittableList::new(Rc::new(BvhNode::from_hittable_list(world)));
+    report.phase("bvh build", phase_start.elapsed());
+
+    if let Some((i, j)) = opts.pick {
+        cam.initialize(&world);
+        match cam.pick(&world, i, j) {
+            Some(rec) => println!("object {} at t = {:.4}, p = {:?}, normal = {:?}, albedo = {:?}",
+                                  rec.object_id, rec.t, rec.p, rec.normal, rec.mat.albedo(&rec)),
+            None => println!("nothing hit through pixel {},{}", i, j),
         }
+        return Ok(());
     }
 
-    let mat1 = Rc::new(Dielectric::new(1.333, 0.1));
-    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 1.0, 0.0), 1.0, mat1)));
+    if opts.interactive {
+        return viewer::run(&mut cam, &world, scene_name);
+    }
 
-    // let mat2 = Rc::new(Lambertian::new(&Color::new(0.7, 0.4697, 0.7)));
-    let mat2 = Rc::new(Dielectric::new(1.333, 0.2));
-    world.add(Rc::new(Sphere::new(&Vec3::new(-4.0, 1.0, 0.0), 1.0, mat2)));
+    if opts.animate {
+        let mut animation = match (opts.turntable, animation) {
+            (Some(seconds), _) => CameraAnimation::turntable(&cam, seconds),
+            (None, Some(animation)) => animation,
+            (None, None) => {
+                let msg = format!("scene {} has no camera animation, use --turntable <seconds>", scene_name);
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+            }
+        };
+        if let Some(interpolation) = opts.interpolation { animation.interpolation = interpolation; }
+
+        let fps = opts.fps.unwrap_or(24.0);
+        let (first, last) = opts.frames.unwrap_or((1, animation.frame_count(fps)));
+        let denoiser = Denoiser {
+            iterations: opts.denoise_iterations.unwrap_or(Denoiser::default().iterations),
+            ..Denoiser::default()
+        };
+
+        let phase_start = Instant::now();
+        animation::render_sequence(&mut cam, &world, &animation, first..=last, fps,
+                                   opts.output.as_deref().unwrap_or("frame.ppm"), opts.skip_existing,
+                                   opts.denoise.then_some(&denoiser))?;
+        report.phase("render", phase_start.elapsed());
+        report.print(&mut stderr())?;
+        return Ok(());
+    }
 
-    // let mat3 = Rc::new(Metal::new(&Color::new(0.7, 0.6, 0.5), 0.1));
-    let mat3 = Rc::new(Dielectric::new(1.333, 0.01));
-    world.add(Rc::new(Sphere::new(&Vec3::new(4.0, 1.0, 0.0), 1.0, mat3)));
+    let phase_start = Instant::now();
+    let film = if let Some(addr) = &opts.coordinator {
+        // workers build their own copy of the scene
+        distributed::coordinate(&mut cam, &world, scene_name, scene_hash, &opts.worker_args, addr,
+                                opts.tile_size.unwrap_or(32), opts.spawn_workers)?
+    } else if let Some(path) = &opts.resume {
+        let film = Checkpoint::load(path)?.resume(scene_hash)?;
+        cam.render_onto(&world, film)?
+    } else if let Some(path) = &opts.load_film {
+        cam.render_onto(&world, Film::load(path)?)?
+    } else {
+        cam.render(&world)?
+    };
+    report.phase("render", phase_start.elapsed());
+
+    let phase_start = Instant::now();
+    let denoised = match (opts.denoise, cam.aovs()) {
+        (true, Some(aovs)) => {
+            let mut denoiser = Denoiser::default();
+            if let Some(iterations) = opts.denoise_iterations { denoiser.iterations = iterations; }
+            Some(denoiser.denoise(&film, aovs))
+        }
+        _ => None,
+    };
 
-    world = HittableList::new(Rc::new(BvhNode::from_hittable_list(world)));
+    if let Some(path) = &opts.output {
+        let mut out = BufWriter::new(File::create(path)?);
+        denoised.as_ref().unwrap_or(&film).write_ppm(&mut out, cam.display_exposure(), cam.tone_map)?;
 
-    let mut cam = Camera::default();
-
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.img_width = 400;
-    cam.samples_per_pixel = 32;
-    cam.max_depth = 8;
-
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
-
-    cam.render(&world)?;
-    */
-
-    let left_red =     Rc::new(Lambertian::new(&Color::new(1.0, 0.2, 0.2)));
-    let back_green =   Rc::new(Lambertian::new(&Color::new(0.2, 1.0, 0.2)));
-    let right_blue =   Rc::new(Lambertian::new(&Color::new(0.2, 0.2, 1.0)));
-    let upper_orange = Rc::new(Lambertian::new(&Color::new(1.0, 0.5, 0.0)));
-    let lower_teal =   Rc::new(Lambertian::new(&Color::new(0.2, 0.8, 0.8)));
-
-    world.add(Rc::new(Quad::new(&Vec3::new(-3.0, -2.0, 5.0), 
-                                &Vec3::new(0.0, 0.0, -4.0),
-                                &Vec3::new(0.0, 4.0, 0.0),
-                                left_red)));
-    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, -2.0, 0.0), 
-                                &Vec3::new(4.0, 0.0, 0.0),
-                                &Vec3::new(0.0, 4.0, 0.0),
-                                back_green)));
-    world.add(Rc::new(Quad::new(&Vec3::new(3.0, -2.0, 1.0), 
-                                &Vec3::new(0.0, 0.0, 4.0),
-                                &Vec3::new(0.0, 4.0, 0.0),
-                                right_blue)));
-    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, 3.0, 1.0), 
-                                &Vec3::new(4.0, 0.0, 0.0),
-                                &Vec3::new(0.0, 0.0, 4.0),
-                                upper_orange)));
-    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, -3.0, 5.0), 
-                                &Vec3::new(4.0, 0.0, 0.0),
-                                &Vec3::new(0.0, 0.0, -4.0),
-                                lower_teal)));
-
-    let mut cam = Camera::default();
-
-    cam.aspect_ratio = 1.0;
-    cam.img_width = 400;
-    cam.samples_per_pixel = 100;
-    cam.max_depth = 50;
-
-    cam.vfov = 80.0;
-    cam.lookfrom = Vec3::new(0.0, 0.0, 9.0);
-    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
+        // keep the raw render next to the denoised one
+        if denoised.is_some() {
+            let mut out = BufWriter::new(File::create(denoise::noisy_path(path))?);
+            film.write_ppm(&mut out, cam.display_exposure(), cam.tone_map)?;
+        }
+    }
+    if let Some(path) = &opts.save_film {
+        film.save(path)?;
+    }
+    if let (Some(prefix), Some(aovs)) = (&opts.aov_prefix, cam.aovs()) {
+        aovs.write_all(prefix)?;
+    }
+    report.phase("output", phase_start.elapsed());
 
-    world = HittableList::new(Rc::new(BvhNode::from_hittable_list(world)));
+    if let Some(denoised) = &denoised {
+        cam.display(denoised);
+    }
 
-    cam.render(&world);
+    report.print(&mut stderr())?;
+    if let Some(path) = &opts.stats_json {
+        std::fs::write(path, report.to_json())?;
+    }
 
-    // printing done in render because loop occurs there
-    // stderr().write_all(format!{"execution took: {:?}\n", end_time - start_time}.as_bytes())?;
+    cam.wait_for_window();
 
     Ok(())
 }
@@ -1,15 +1,48 @@
-use std::io::Write;
+use std::hash::Hasher;
+use std::rc::Rc;
 
 use crate::libs::rand_double;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::HitRecord;
 use crate::color::Color;
+use crate::microfacet::{fresnel_dielectric, sample_dielectric, sample_reflection, Ggx};
+use crate::onb::Onb;
+use crate::spectrum::Ior;
+use crate::texture::{self, Texture};
+use crate::thin_film::{Substrate, ThinFilm};
+use crate::tonemap::luminance;
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _attenuation: &mut Color, _scattered: &mut Ray) -> bool {
+        false
+    }
+
+    // normal used for shading, lets normal / bump maps replace the
+    // geometric one. called once per hit before scatter
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+
+    // surface color for the albedo aov / denoiser guide
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    // radiance given off towards r_in, black unless the material is a light
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // true if scatter already put the scattered ray where it has to start.
+    // otherwise the renderer nudges it off the surface at rec.p, which is
+    // wrong for e.g. a subsurface walk that leaves somewhere else
+    fn places_scattered(&self, _rec: &HitRecord) -> bool {
         false
     }
+
+    // see Hittable::hash_into
+    fn hash_into(&self, state: &mut dyn Hasher);
 }
 
 pub struct Lambertian {
@@ -26,30 +59,43 @@ impl Lambertian {
 pub struct Metal {
     albedo: Color,
     fuzz:   f64,
+    film:   Option<ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: &Color, fuzz: f64) -> Self {
-        Self { albedo: *albedo, fuzz: if fuzz < 1.0 { fuzz } else { 1.0 } }
+        Self { albedo: *albedo, fuzz: if fuzz < 1.0 { fuzz } else { 1.0 }, film: None }
+    }
+
+    // iridescent coating, e.g. heat tinted or anodized metal
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
     }
 }
 
 
 pub struct Dielectric {
-    ior: f64,
+    ior: Ior,
     fuzz: f64,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ior: f64, fuzz: f64) -> Self {
-        Self { ior, fuzz }
+        Self { ior: Ior::Constant(ior), fuzz, film: None }
+    }
+
+    // wavelength dependent ior, disperses in spectral mode
+    pub fn dispersive(ior: Ior, fuzz: f64) -> Self {
+        Self { ior, fuzz, film: None }
     }
 
-    // Schlik approximation for reflectance
-    pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
-        let mut r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
-        r0 = r0*r0;
-        return r0 + (1.0 - r0) * f64::powi(1.0-cosine, 5);
+    // coated glass, the film replaces the fresnel reflectance and tints
+    // reflection and refraction in complementary colors
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
     }
 }
 
@@ -62,10 +108,19 @@ impl Material for Lambertian {
             scatter_dir = rec.normal;
         }
 
-        *scattered = Ray::new(rec.p, scatter_dir);
+        *scattered = r_in.spawn(rec.p, scatter_dir);
         *attenuation = self.albedo;
         return true;
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"lambertian");
+        self.albedo.hash_into(state);
+    }
 }
 
 impl Material for Metal {
@@ -73,16 +128,34 @@ impl Material for Metal {
         let mut reflected = Vec3::reflect(&r_in.direction(), &rec.normal);
         reflected = reflected.unit_vector() + (self.fuzz * Vec3::random_unit_vector());
         
-        *scattered = Ray::new(rec.p, reflected);
-        *attenuation = self.albedo;
+        *scattered = r_in.spawn(rec.p, reflected);
+        *attenuation = match &self.film {
+            Some(film) => {
+                let cos_i = -r_in.direction().unit_vector().dot(&rec.normal);
+                film.reflectance(cos_i, 1.0, &Substrate::Conductor(self.albedo), r_in.wavelength())
+            }
+            None => self.albedo,
+        };
         return scattered.direction().dot(&rec.normal) > 0.0;
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"metal");
+        self.albedo.hash_into(state);
+        state.write_u64(self.fuzz.to_bits());
+        if let Some(film) = &self.film { film.hash_into(state); }
+    }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
         *attenuation = Color::new(1.0, 1.0, 1.0);
-        let ri = if rec.front_face {1.0 / self.ior} else {self.ior};
+        let ior = self.ior.at(r_in.wavelength());
+        let ri = if rec.front_face {1.0 / ior} else {ior};
 
         let unit_dir = r_in.direction().unit_vector();
         let cos_theta = f64::min((-unit_dir).dot(&rec.normal), 1.0);
@@ -91,14 +164,283 @@ impl Material for Dielectric {
         let cannot_refract = ri * sin_theta > 1.0;
         let mut dir: Vec3;
 
-        if cannot_refract || (Dielectric::reflectance(cos_theta, ri) > rand_double()) {
+        if let Some(film) = self.film.filter(|_| !cannot_refract) {
+            // pick by the mean reflectance, weight by the per channel one
+            let (outside, inside) = if rec.front_face { (1.0, ior) } else { (ior, 1.0) };
+            let r = film.reflectance(cos_theta, outside, &Substrate::Dielectric(inside), r_in.wavelength());
+            let p = ((r.x() + r.y() + r.z()) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+            if rand_double() < p {
+                dir = Vec3::reflect(&unit_dir, &rec.normal);
+                *attenuation = r / p;
+            } else {
+                dir = Vec3::refract(&unit_dir, &rec.normal, ri);
+                *attenuation = (Color::new(1.0, 1.0, 1.0) - r) / (1.0 - p);
+            }
+        } else if cannot_refract || fresnel_dielectric(cos_theta, 1.0 / ri) > rand_double() {
+            // the same fresnel as RoughDielectric, which this is at roughness 0
             dir = Vec3::reflect(&unit_dir, &rec.normal);
         } else {
             dir = Vec3::refract(&unit_dir, &rec.normal, ri);
         }
 
         dir += Vec3::random_unit_vector() * self.fuzz;
-        *scattered = Ray::new(rec.p, dir);
+        *scattered = r_in.spawn(rec.p, dir);
+        return true;
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"dielectric");
+        self.ior.hash_into(state);
+        state.write_u64(self.fuzz.to_bits());
+        if let Some(film) = &self.film { film.hash_into(state); }
+    }
+}
+
+
+// emits on both faces and does not scatter. wrap in TwoSided with a black
+// back for a light that only shines one way
+pub struct DiffuseLight {
+    emit: Rc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: &Color) -> Self {
+        Self { emit: texture::solid(emit) }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        self.emit.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"diffuse_light");
+        self.emit.hash_into(state);
+    }
+}
+
+
+// separate materials for the front (the side the outward normal points to,
+// outside of a sphere) and the back face of a shape
+pub struct TwoSided {
+    front: Rc<dyn Material>,
+    back: Rc<dyn Material>,
+}
+
+impl TwoSided {
+    pub fn new(front: Rc<dyn Material>, back: Rc<dyn Material>) -> Self {
+        Self { front, back }
+    }
+
+    fn side(&self, rec: &HitRecord) -> &Rc<dyn Material> {
+        if rec.front_face { &self.front } else { &self.back }
+    }
+}
+
+impl Material for TwoSided {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        self.side(rec).scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.side(rec).shading_normal(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.side(rec).albedo(rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.side(rec).emitted(r_in, rec)
+    }
+
+    fn places_scattered(&self, rec: &HitRecord) -> bool {
+        self.side(rec).places_scattered(rec)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"two_sided");
+        self.front.hash_into(state);
+        self.back.hash_into(state);
+    }
+}
+
+
+// soap bubble: a film with air on both sides. light either reflects off it
+// or carries straight on, there is no bending since the sheet is so thin
+pub struct SoapBubble {
+    film: ThinFilm,
+    // red channel picks the thickness between the two values (nm), for
+    // the swirls of draining soap
+    thickness_map: Option<(Rc<dyn Texture>, f64, f64)>,
+}
+
+impl SoapBubble {
+    pub fn new(film: ThinFilm) -> Self {
+        Self { film, thickness_map: None }
+    }
+
+    pub fn with_thickness_map(mut self, map: Rc<dyn Texture>, min: f64, max: f64) -> Self {
+        self.thickness_map = Some((map, min, max));
+        self
+    }
+}
+
+impl Material for SoapBubble {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        let mut film = self.film;
+        if let Some((map, min, max)) = &self.thickness_map {
+            let t = map.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0);
+            film.thickness = min + (max - min) * t;
+        }
+
+        let unit_dir = r_in.direction().unit_vector();
+        let cos_i = (-unit_dir).dot(&rec.normal);
+        let r = film.reflectance(cos_i, 1.0, &Substrate::Dielectric(1.0), r_in.wavelength());
+        let p = ((r.x() + r.y() + r.z()) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+
+        if rand_double() < p {
+            *scattered = r_in.spawn(rec.p, Vec3::reflect(&unit_dir, &rec.normal));
+            *attenuation = r / p;
+        } else {
+            *scattered = r_in.spawn(rec.p, unit_dir);
+            *attenuation = (Color::new(1.0, 1.0, 1.0) - r) / (1.0 - p);
+        }
+        return true;
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"soap_bubble");
+        self.film.hash_into(state);
+        if let Some((map, min, max)) = &self.thickness_map {
+            map.hash_into(state);
+            state.write_u64(min.to_bits());
+            state.write_u64(max.to_bits());
+        }
+    }
+}
+
+
+// disney-principled style material. every parameter is a texture so it can
+// vary over the surface, scalar parameters read the red channel. lobes are
+// picked stochastically in layers: clearcoat on top, then metal, glass or
+// a glossy dielectric over a diffuse (+ sheen) base
+pub struct Principled {
+    pub base_color: Rc<dyn Texture>,
+    pub metallic: Rc<dyn Texture>,
+    pub roughness: Rc<dyn Texture>,
+    // strength of the dielectric highlight, 0.5 is a reflectance of 4%
+    pub specular: Rc<dyn Texture>,
+    // grazing retro-reflection for cloth, tinted towards the base color
+    pub sheen: Rc<dyn Texture>,
+    pub sheen_tint: Rc<dyn Texture>,
+    // second, colorless glossy layer with its own roughness
+    pub clearcoat: Rc<dyn Texture>,
+    pub clearcoat_roughness: Rc<dyn Texture>,
+    // fraction of the non-metallic part that is glass. needs closed shapes
+    pub transmission: Rc<dyn Texture>,
+    pub ior: f64,
+    pub anisotropy: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: texture::constant(0.8),
+            metallic: texture::constant(0.0),
+            roughness: texture::constant(0.5),
+            specular: texture::constant(0.5),
+            sheen: texture::constant(0.0),
+            sheen_tint: texture::constant(0.5),
+            clearcoat: texture::constant(0.0),
+            clearcoat_roughness: texture::constant(0.03),
+            transmission: texture::constant(0.0),
+            ior: 1.5,
+            anisotropy: 0.0,
+        }
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: &Color) -> Self {
+        Self { base_color: texture::solid(base_color), ..Self::default() }
+    }
+
+    fn schlick(f0: &Color, cosine: f64) -> Color {
+        let weight = f64::powi(1.0 - cosine.clamp(0.0, 1.0), 5);
+        *f0 + (Color::new(1.0, 1.0, 1.0) - *f0) * weight
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        let scalar = |t: &Rc<dyn Texture>| t.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0);
+        let base = self.base_color.value(rec.u, rec.v, &rec.p);
+        let roughness = scalar(&self.roughness);
+        let transmission = scalar(&self.transmission);
+
+        let frame = Onb::from_normal_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 { return false; }
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let glossy = Ggx::new(roughness, self.anisotropy);
+
+        // rays inside a transmissive object got there through the glass lobe
+        let inside = !rec.front_face && transmission > 0.0;
+
+        let sample = if !inside && rand_double() < scalar(&self.clearcoat) * fresnel_dielectric(wo.z(), 1.5) {
+            let coat = Ggx::new(scalar(&self.clearcoat_roughness), 0.0);
+            sample_reflection(&coat, &wo).map(|(wi, weight)| (wi, white * weight))
+        } else if !inside && rand_double() < scalar(&self.metallic) {
+            sample_reflection(&glossy, &wo).map(|(wi, weight)| {
+                let h = (wo + wi).unit_vector();
+                (wi, Self::schlick(&base, wo.dot(&h)) * weight)
+            })
+        } else if inside || rand_double() < transmission {
+            let eta = if rec.front_face { self.ior } else { 1.0 / self.ior };
+            // tinted by the base color over a full pass through
+            let tint = Color::new(base.x().sqrt(), base.y().sqrt(), base.z().sqrt());
+            sample_dielectric(&glossy, roughness <= 0.01, &wo, eta)
+                .map(|(wi, weight)| (wi, if wi.z() < 0.0 { tint * weight } else { white * weight }))
+        } else {
+            let f0 = 0.08 * scalar(&self.specular);
+            let specular_ior = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+
+            if rand_double() < fresnel_dielectric(wo.z(), specular_ior) {
+                sample_reflection(&glossy, &wo).map(|(wi, weight)| (wi, white * weight))
+            } else {
+                let mut wi = Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector();
+                if wi.near_zero() { wi = Vec3::new(0.0, 0.0, 1.0); }
+                let wi = wi.unit_vector();
+
+                let lum = luminance(&base);
+                let tint_color = if lum > 0.0 { base / lum } else { white };
+                let sheen_color = white + (tint_color - white) * scalar(&self.sheen_tint);
+                let cos_d = wi.dot(&(wo + wi).unit_vector());
+
+                Some((wi, base + sheen_color * (scalar(&self.sheen) * f64::powi(1.0 - cos_d, 5))))
+            }
+        };
+
+        let Some((wi, weight)) = sample else { return false; };
+        *attenuation = weight;
+        *scattered = r_in.spawn(rec.p, frame.to_world(&wi));
         return true;
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"principled");
+        for texture in [&self.base_color, &self.metallic, &self.roughness, &self.specular, &self.sheen, &self.sheen_tint,
+                        &self.clearcoat, &self.clearcoat_roughness, &self.transmission] {
+            texture.hash_into(state);
+        }
+        state.write_u64(self.ior.to_bits());
+        state.write_u64(self.anisotropy.to_bits());
+    }
 }
@@ -0,0 +1,233 @@
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use crate::aabb::AABB;
+use crate::bvh_node::BvhNode;
+use crate::hittable::*;
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::libs::{next_object_id, Fnv1a};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::stats;
+use crate::texture::AlphaMask;
+use crate::vec3::Vec3;
+
+// one triangle of a mesh as indices into the mesh's vertex arrays. without
+// texture coordinates the barycentrics are used, without normals the
+// triangle is shaded flat
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub uvs: Option<[usize; 3]>,
+    pub normals: Option<[usize; 3]>,
+    // into the mesh's material slots
+    pub material: usize,
+}
+
+// what faces refer to by their material index. a TwoSided material gives
+// the faces different materials on the side their winding turns away from
+#[derive(Clone)]
+pub struct MeshMaterial {
+    pub mat: Rc<dyn Material>,
+    // cut holes where the mask is transparent at the interpolated uvs,
+    // e.g. for leaves modelled as a few quads
+    pub mask: Option<AlphaMask>,
+}
+
+impl MeshMaterial {
+    pub fn new(mat: Rc<dyn Material>) -> Self {
+        Self { mat, mask: None }
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        self.mat.hash_into(state);
+        if let Some(mask) = &self.mask { mask.hash_into(state); }
+    }
+}
+
+// vertex data shared by all triangles of a mesh
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    materials: Vec<MeshMaterial>,
+    id: u32,
+}
+
+// triangle mesh with its own bvh over the faces. the whole mesh is one
+// object for picking and the object id aov
+pub struct Mesh {
+    tree: BvhNode,
+    data: Rc<MeshData>,
+    // of the vertices and faces, stands in for them in the scene hash
+    digest: u64,
+}
+
+impl Mesh {
+    // indices in faces have to be valid for the given arrays and material
+    // slots, see obj::load for a loader that checks them
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, faces: &[Face], materials: Vec<MeshMaterial>) -> Self {
+        let mut digest = Fnv1a::default();
+        for x in positions.iter().chain(&normals).flat_map(|v| [v.x(), v.y(), v.z()]).chain(uvs.iter().flat_map(|uv| [uv.0, uv.1])) {
+            digest.write_u64(x.to_bits());
+        }
+        for face in faces {
+            for i in face.positions.iter().chain(face.uvs.iter().flatten()).chain(face.normals.iter().flatten()) {
+                digest.write_usize(*i);
+            }
+            digest.write_usize(face.material);
+            // keeps faces with and without uvs / normals apart
+            digest.write_u8(face.uvs.is_some() as u8 | (face.normals.is_some() as u8) << 1);
+        }
+
+        let data = Rc::new(MeshData { positions, normals, uvs, materials, id: next_object_id() });
+        let mut triangles = HittableList::default();
+        for face in faces {
+            triangles.add(Rc::new(Triangle::new(data.clone(), *face)));
+        }
+        Self { tree: BvhNode::from_hittable_list(triangles), data, digest: digest.finish() }
+    }
+
+    // closed axis aligned cube with outward facing triangles, e.g. as a
+    // watertight subsurface boundary
+    pub fn cube(center: &Vec3, size: f64, mat: Rc<dyn Material>) -> Self {
+        // corner i has x, y and z from bits 0, 1 and 2
+        let positions = (0..8).map(|i| {
+            let corner = Vec3::new((i & 1) as f64, (i >> 1 & 1) as f64, (i >> 2 & 1) as f64) - Vec3::new(0.5, 0.5, 0.5);
+            *center + corner * size
+        }).collect();
+        let quads = [[0, 4, 6, 2], [1, 3, 7, 5], [0, 1, 5, 4], [2, 6, 7, 3], [0, 2, 3, 1], [4, 5, 7, 6]];
+        let faces: Vec<Face> = quads.iter()
+            .flat_map(|[a, b, c, d]| [[*a, *b, *c], [*a, *c, *d]])
+            .map(|positions| Face { positions, ..Face::default() })
+            .collect();
+        Self::new(positions, Vec::new(), Vec::new(), &faces, vec![MeshMaterial::new(mat)])
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.tree.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.tree.bounding_box()
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"mesh");
+        state.write_u64(self.digest);
+        state.write_usize(self.data.materials.len());
+        for material in &self.data.materials {
+            material.hash_into(state);
+        }
+    }
+}
+
+struct Triangle {
+    mesh: Rc<MeshData>,
+    face: Face,
+    bbox: AABB,
+}
+
+impl Triangle {
+    fn new(mesh: Rc<MeshData>, face: Face) -> Self {
+        let [a, b, c] = face.positions.map(|i| mesh.positions[i]);
+        let bbox = AABB::from_boxes(&AABB::from_points(a, b), &AABB::from_points(c, c));
+        Self { mesh, face, bbox }
+    }
+
+    fn uv(&self, vertex: usize) -> (f64, f64) {
+        match self.face.uvs {
+            Some(uvs) => self.mesh.uvs[uvs[vertex]],
+            // the triangle's own barycentric parameterization
+            None => [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)][vertex],
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::count_primitive_test();
+        let [p0, p1, p2] = self.face.positions.map(|i| self.mesh.positions[i]);
+
+        // moller-trumbore
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+        let pvec = r.direction().cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < 1e-12 { return false; }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) { return false; }
+
+        let qvec = tvec.cross(&e1);
+        let b2 = r.direction().dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 { return false; }
+
+        let t = e2.dot(&qvec) * inv_det;
+        if !ray_t.contains(t) { return false; }
+        let b0 = 1.0 - b1 - b2;
+        let p = r.at(t);
+
+        // texture coordinates and the tangent frame they imply
+        let (uv0, uv1, uv2) = (self.uv(0), self.uv(1), self.uv(2));
+        let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+        let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+
+        // ray passes through a cutout
+        let slot = &self.mesh.materials[self.face.material];
+        if let Some(mask) = &slot.mask {
+            if !mask.opaque(u, v, &p) { return false; }
+        }
+
+        let uv_det = du1 * dv2 - dv1 * du2;
+        let (dpdu, dpdv) = if uv_det.abs() < 1e-12 {
+            (e1, e2)
+        } else {
+            ((e1 * dv2 - e2 * dv1) / uv_det, (e2 * du1 - e1 * du2) / uv_det)
+        };
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+        rec.mat = slot.mat.clone();
+        rec.object_id = self.mesh.id;
+        // counter-clockwise winding faces the front
+        rec.set_face_normal(r, &e1.cross(&e2).unit_vector());
+
+        // smooth shading, turned to the same side as the geometric normal
+        if let Some(normals) = self.face.normals {
+            let [n0, n1, n2] = normals.map(|i| self.mesh.normals[i]);
+            let n = n0 * b0 + n1 * b1 + n2 * b2;
+            if !n.near_zero() {
+                let n = n.unit_vector();
+                rec.normal = if n.dot(&rec.geometric_normal) < 0.0 { -n } else { n };
+            }
+        }
+
+        stats::count_hit("triangle");
+        return true;
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    // only reached through a Mesh, which covers the vertex data
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"triangle");
+        for i in self.face.positions {
+            state.write_usize(i);
+        }
+        state.write_usize(self.face.material);
+    }
+}
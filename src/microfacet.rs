@@ -0,0 +1,354 @@
+use std::hash::Hasher;
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::libs::*;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::spectrum::Ior;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// anisotropic GGX / Trowbridge-Reitz distribution with the Smith
+// height-correlated masking-shadowing term. vectors are in the local
+// shading frame, z along the normal
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    // roughness in [0, 1] is perceptually linear (alpha = roughness^2),
+    // anisotropy in [0, 1) stretches the highlight along the tangent
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = f64::sqrt(1.0 - 0.9 * anisotropy.clamp(0.0, 1.0));
+        // perfectly smooth would divide by zero below
+        Self { alpha_x: f64::max(alpha / aspect, 1e-4), alpha_y: f64::max(alpha * aspect, 1e-4) }
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 { return INFINITY; }
+        let (x, y) = (self.alpha_x * w.x(), self.alpha_y * w.y());
+        (f64::sqrt(1.0 + (x*x + y*y) / z2) - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // microfacet normal sampled from the normals visible from wo
+    // (Heitz 2018), wo in the upper hemisphere
+    pub fn sample_visible_normal(&self, wo: &Vec3) -> Vec3 {
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+
+        let len_sq = vh.x()*vh.x() + vh.y()*vh.y();
+        let t1 = if len_sq > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / f64::sqrt(len_sq) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(&t1);
+
+        let r = f64::sqrt(rand_double());
+        let phi = 2.0 * PI * rand_double();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * f64::sqrt(1.0 - p1*p1) + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + f64::sqrt(f64::max(0.0, 1.0 - p1*p1 - p2*p2)) * vh;
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), f64::max(0.0, nh.z())).unit_vector()
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write_u64(self.alpha_x.to_bits());
+        state.write_u64(self.alpha_y.to_bits());
+    }
+}
+
+// unpolarized fresnel reflectance of a dielectric interface. cos_i is on
+// the incident side, eta = n_transmitted / n_incident
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i*cos_i) / (eta*eta);
+    if sin2_t >= 1.0 { return 1.0; }
+
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs*rs + rp*rp) / 2.0
+}
+
+// unpolarized fresnel reflectance of a conductor with complex ior eta + ik
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    let t0 = eta*eta - k*k - sin2;
+    let a2_plus_b2 = f64::sqrt(t0*t0 + 4.0*eta*eta*k*k);
+    let a = f64::sqrt(f64::max(0.0, (a2_plus_b2 + t0) / 2.0));
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2*sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (rs + rp) / 2.0
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Fresnel {
+    // complex ior per channel
+    Conductor { eta: Color, k: Color },
+    // ior of a clear coat over the base color
+    Dielectric(f64),
+}
+
+impl Fresnel {
+    pub fn evaluate(&self, cos_i: f64) -> Color {
+        match self {
+            Fresnel::Conductor { eta, k } => Color::new(fresnel_conductor(cos_i, eta.x(), k.x()),
+                                                        fresnel_conductor(cos_i, eta.y(), k.y()),
+                                                        fresnel_conductor(cos_i, eta.z(), k.z())),
+            Fresnel::Dielectric(ior) => {
+                let f = fresnel_dielectric(cos_i, *ior);
+                Color::new(f, f, f)
+            }
+        }
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        match self {
+            Fresnel::Conductor { eta, k } => {
+                state.write(b"conductor");
+                eta.hash_into(state);
+                k.hash_into(state);
+            }
+            Fresnel::Dielectric(ior) => {
+                state.write(b"dielectric");
+                state.write_u64(ior.to_bits());
+            }
+        }
+    }
+}
+
+// GGX microfacet reflection. conductors reflect everything that is not
+// absorbed, tinted by the fresnel term; dielectrics (plastics) pick between
+// the glossy coat and the diffuse base with the coat's fresnel reflectance
+pub struct Microfacet {
+    fresnel: Fresnel,
+    distribution: Ggx,
+    // color under a dielectric coat, tint on top of a conductor
+    base: Color,
+}
+
+impl Microfacet {
+    pub fn conductor(eta: &Color, k: &Color, roughness: f64, anisotropy: f64) -> Self {
+        Self { fresnel: Fresnel::Conductor { eta: *eta, k: *k },
+               distribution: Ggx::new(roughness, anisotropy),
+               base: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn plastic(base: &Color, ior: f64, roughness: f64, anisotropy: f64) -> Self {
+        Self { fresnel: Fresnel::Dielectric(ior), distribution: Ggx::new(roughness, anisotropy), base: *base }
+    }
+
+    // measured complex ior at roughly 650, 550 and 450nm
+    pub fn gold(roughness: f64) -> Self {
+        Self::conductor(&Color::new(0.143, 0.374, 1.442), &Color::new(3.983, 2.385, 1.603), roughness, 0.0)
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::conductor(&Color::new(0.155, 0.117, 0.138), &Color::new(4.828, 3.122, 2.147), roughness, 0.0)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::conductor(&Color::new(0.200, 0.924, 1.102), &Color::new(3.912, 2.452, 2.142), roughness, 0.0)
+    }
+}
+
+// importance samples the visible normals and reflects about them.
+// returns (direction, weight = f * cos / pdf without fresnel) in the local frame
+pub fn sample_reflection(distribution: &Ggx, wo: &Vec3) -> Option<(Vec3, f64)> {
+    let h = distribution.sample_visible_normal(wo);
+    let wi = Vec3::reflect(&-*wo, &h);
+    if wi.z() <= 0.0 { return None; }
+
+    // D and the pdf cancel, leaving the shadowing of the sampled direction
+    Some((wi, distribution.g2(wo, &wi) / distribution.g1(wo)))
+}
+
+// reflection or refraction through a rough dielectric interface, picked
+// with the sampled microfacet's fresnel reflectance so the fresnel term
+// cancels too. eta = n_transmitted / n_incident, smooth skips the
+// distribution and uses the macro normal
+pub fn sample_dielectric(distribution: &Ggx, smooth: bool, wo: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let h = if smooth { Vec3::new(0.0, 0.0, 1.0) } else { distribution.sample_visible_normal(wo) };
+
+    let wi = if rand_double() < fresnel_dielectric(wo.dot(&h), eta) {
+        let wi = Vec3::reflect(&-*wo, &h);
+        if wi.z() <= 0.0 { return None; }
+        wi
+    } else {
+        let wi = Vec3::refract(&-*wo, &h, 1.0 / eta);
+        // refracted back out the side it came from
+        if wi.z() >= 0.0 { return None; }
+        wi
+    };
+
+    let weight = if smooth { 1.0 } else { distribution.g2(wo, &wi) / distribution.g1(wo) };
+    Some((wi, weight))
+}
+
+impl Material for Microfacet {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        let frame = Onb::from_normal_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 { return false; }
+
+        let wi = match self.fresnel {
+            Fresnel::Conductor { .. } => {
+                let Some((wi, weight)) = sample_reflection(&self.distribution, &wo) else { return false; };
+                let h = (wo + wi).unit_vector();
+                *attenuation = self.base * self.fresnel.evaluate(wo.dot(&h)) * weight;
+                wi
+            }
+            Fresnel::Dielectric(ior) => {
+                // the coat reflects about fresnel_dielectric of the light,
+                // the rest reaches the base
+                if rand_double() < fresnel_dielectric(wo.z(), ior) {
+                    let Some((wi, weight)) = sample_reflection(&self.distribution, &wo) else { return false; };
+                    *attenuation = Color::new(weight, weight, weight);
+                    wi
+                } else {
+                    *attenuation = self.base;
+                    Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector()
+                }
+            }
+        };
+
+        if wi.near_zero() { return false; }
+        *scattered = r_in.spawn(rec.p, frame.to_world(&wi));
+        return true;
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        match self.fresnel {
+            Fresnel::Conductor { .. } => self.base * self.fresnel.evaluate(1.0),
+            Fresnel::Dielectric(_) => self.base,
+        }
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"microfacet");
+        self.fresnel.hash_into(state);
+        self.distribution.hash_into(state);
+        self.base.hash_into(state);
+    }
+}
+
+// glass with a GGX rough surface. each interaction picks reflection or
+// refraction about a sampled visible microfacet normal with that normal's
+// fresnel reflectance. at roughness 0 this is the smooth Dielectric
+pub struct RoughDielectric {
+    ior: Ior,
+    distribution: Ggx,
+    smooth: bool,
+    // per-unit-length absorption inside the glass (Beer-Lambert)
+    sigma_a: Color,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: f64, roughness: f64) -> Self {
+        Self::dispersive(Ior::Constant(ior), roughness)
+    }
+
+    pub fn dispersive(ior: Ior, roughness: f64) -> Self {
+        Self { ior, distribution: Ggx::new(roughness, 0.0), smooth: roughness <= 0.01, sigma_a: Color::default() }
+    }
+
+    // light that travels `distance` through the glass comes out as `color`
+    pub fn with_absorption(mut self, color: &Color, distance: f64) -> Self {
+        let sigma = |c: f64| -f64::ln(c.clamp(1e-6, 1.0)) / distance;
+        self.sigma_a = Color::new(sigma(color.x()), sigma(color.y()), sigma(color.z()));
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        // n_transmitted / n_incident
+        let ior = self.ior.at(r_in.wavelength());
+        let eta = if rec.front_face { ior } else { 1.0 / ior };
+
+        // a ray hitting the inside has just crossed the glass
+        *attenuation = if rec.front_face {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            let distance = rec.t * r_in.direction().length();
+            Color::new(f64::exp(-self.sigma_a.x() * distance),
+                       f64::exp(-self.sigma_a.y() * distance),
+                       f64::exp(-self.sigma_a.z() * distance))
+        };
+
+        let frame = Onb::from_normal_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 { return false; }
+
+        let Some((wi, weight)) = sample_dielectric(&self.distribution, self.smooth, &wo, eta) else { return false; };
+        *attenuation *= weight;
+
+        *scattered = r_in.spawn(rec.p, frame.to_world(&wi));
+        return true;
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"rough_dielectric");
+        self.ior.hash_into(state);
+        self.distribution.hash_into(state);
+        state.write_u8(self.smooth as u8);
+        self.sigma_a.hash_into(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dielectric_fresnel() {
+        // ((n - 1) / (n + 1))^2 head on, from either side
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+        // everything at grazing incidence and past the critical angle
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        // matched media don't reflect
+        assert!(fresnel_dielectric(0.3, 1.0).abs() < 1e-12);
+
+        let mut last = 0.0;
+        for i in (0..=10).rev() {
+            let f = fresnel_dielectric(i as f64 / 10.0, 1.5);
+            assert!(f >= last);
+            last = f;
+        }
+    }
+
+    #[test]
+    fn conductor_fresnel() {
+        // k = 0 is a dielectric
+        for cos_i in [1.0, 0.7, 0.2] {
+            assert!((fresnel_conductor(cos_i, 1.5, 0.0) - fresnel_dielectric(cos_i, 1.5)).abs() < 1e-9);
+        }
+        // ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2) head on
+        let (n, k) = (0.2, 3.0);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, n, k) - expected).abs() < 1e-9);
+        assert!((fresnel_conductor(0.0, n, k) - 1.0).abs() < 1e-9);
+    }
+}
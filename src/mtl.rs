@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::material::{Material, Principled, TwoSided};
+use crate::mesh::MeshMaterial;
+use crate::normal_map::{NormalMapped, SurfaceMap};
+use crate::texture::{self, AlphaMask, ImageTexture, LuminanceAlpha, Texture};
+
+// wavefront .mtl library as Principled materials, by name. understands the
+// classic Kd / Ks / Ns / Ni / d / Tr statements, the PBR extension (Pr, Pm,
+// Ps, Pc, Pcr, aniso) including their map_ textures, and norm / bump maps.
+// texture paths are relative to the .mtl file and have to be ppm / pam
+// images. map_d is a grayscale opacity map and becomes a cutout mask.
+// `back <name>` (not part of the format) makes a material two-sided with
+// another material of the library on the back faces
+pub fn load(path: &str) -> Result<HashMap<String, MeshMaterial>, Error> {
+    let source = fs::read_to_string(path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut materials: HashMap<String, MeshMaterial> = HashMap::new();
+    // material name -> back material name, resolved once all are read
+    let mut backs: Vec<(String, String, usize)> = Vec::new();
+    let mut current: Option<(String, Principled, Option<SurfaceMap>, Option<AlphaMask>)> = None;
+    // roughness from Ns unless Pr / map_Pr says otherwise
+    let mut explicit_roughness = false;
+
+    for (line_no, line) in source.lines().enumerate() {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path, line_no + 1, msg));
+
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else { continue; };
+        if keyword.starts_with('#') { continue; }
+        let args: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, mat, surface, mask)) = current.take() {
+                materials.insert(name, finish(mat, surface, mask));
+            }
+            let name = args.first().ok_or_else(|| invalid("newmtl without a name"))?;
+            current = Some((name.to_string(), Principled::default(), None, None));
+            explicit_roughness = false;
+            continue;
+        }
+
+        let Some((name, mat, surface, mask)) = &mut current else {
+            return Err(invalid("statement before newmtl"));
+        };
+
+        let numbers = || -> Result<Vec<f64>, Error> {
+            args.iter().map(|a| a.parse().map_err(|_| invalid("expected a number"))).collect()
+        };
+        let number = || -> Result<f64, Error> {
+            numbers()?.first().copied().ok_or_else(|| invalid("missing value"))
+        };
+        let color = || -> Result<Color, Error> {
+            match numbers()?.as_slice() {
+                [v] => Ok(Color::new(*v, *v, *v)),
+                [r, g, b, ..] => Ok(Color::new(*r, *g, *b)),
+                _ => Err(invalid("expected a color")),
+            }
+        };
+        // options like -bm come before the file name
+        let map = |srgb: bool| -> Result<Rc<dyn Texture>, Error> {
+            let file = args.last().ok_or_else(|| invalid("missing texture file"))?;
+            let file = dir.join(file);
+            Ok(Rc::new(ImageTexture::load(&file.to_string_lossy(), srgb)?))
+        };
+
+        match keyword {
+            "Kd"     => mat.base_color = texture::solid(&color()?),
+            "map_Kd" => mat.base_color = map(true)?,
+            // the highlight strength, 1 is the principled maximum of 8%
+            "Ks"     => {
+                let ks = color()?;
+                mat.specular = texture::constant(f64::max(ks.x(), f64::max(ks.y(), ks.z())).clamp(0.0, 1.0));
+            }
+            // blinn-phong exponent to ggx roughness
+            "Ns" if !explicit_roughness => {
+                let alpha = f64::sqrt(2.0 / (number()?.max(0.0) + 2.0));
+                mat.roughness = texture::constant(alpha.sqrt());
+            }
+            "Ni"     => mat.ior = number()?,
+            "d"      => mat.transmission = texture::constant((1.0 - number()?).clamp(0.0, 1.0)),
+            "map_d"  => *mask = Some(AlphaMask::new(Rc::new(LuminanceAlpha::new(map(false)?)), 0.5)),
+            "Tr"     => mat.transmission = texture::constant(number()?.clamp(0.0, 1.0)),
+            "Pr"     => { mat.roughness = texture::constant(number()?); explicit_roughness = true; }
+            "map_Pr" => { mat.roughness = map(false)?; explicit_roughness = true; }
+            "Pm"     => mat.metallic = texture::constant(number()?),
+            "map_Pm" => mat.metallic = map(false)?,
+            "Ps"     => mat.sheen = texture::constant(number()?),
+            "map_Ps" => mat.sheen = map(false)?,
+            "Pc"     => mat.clearcoat = texture::constant(number()?),
+            "Pcr"    => mat.clearcoat_roughness = texture::constant(number()?),
+            "aniso"  => mat.anisotropy = number()?,
+            "norm"   => *surface = Some(SurfaceMap::Normal { map: map(false)?, strength: 1.0 }),
+            // -bm is the height of white in model units
+            "bump" | "map_Bump" | "map_bump" => {
+                let scale = match args.iter().position(|a| *a == "-bm") {
+                    Some(i) => args.get(i + 1).and_then(|s| s.parse().ok()).ok_or_else(|| invalid("invalid -bm"))?,
+                    None => 0.01,
+                };
+                *surface = Some(SurfaceMap::Bump { map: map(false)?, scale });
+            }
+            "back"   => {
+                let back = args.first().ok_or_else(|| invalid("back without a material name"))?;
+                backs.push((name.clone(), back.to_string(), line_no + 1));
+            }
+            // illumination models, ambient and emission are not used
+            _ => {}
+        }
+    }
+
+    if let Some((name, mat, surface, mask)) = current {
+        materials.insert(name, finish(mat, surface, mask));
+    }
+
+    // backs refer to the one-sided materials, so chains don't nest
+    let one_sided = materials.clone();
+    for (name, back, line) in backs {
+        let back = one_sided.get(&back).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("{}:{}: unknown material {}", path, line, back))
+        })?;
+        let front = materials.get_mut(&name).unwrap();
+        front.mat = Rc::new(TwoSided::new(front.mat.clone(), back.mat.clone()));
+    }
+
+    Ok(materials)
+}
+
+fn finish(mat: Principled, surface: Option<SurfaceMap>, mask: Option<AlphaMask>) -> MeshMaterial {
+    let mat: Rc<dyn Material> = match surface {
+        Some(surface) => Rc::new(NormalMapped::new(Rc::new(mat), surface)),
+        None => Rc::new(mat),
+    };
+    MeshMaterial { mat, mask }
+}
@@ -0,0 +1,161 @@
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+// world space step for bump maps without a resolution (procedural ones)
+const BUMP_STEP: f64 = 1e-3;
+
+pub enum SurfaceMap {
+    // rgb encodes a tangent space normal (x along dpdu, y along dpdv, z
+    // out of the surface) as 0.5 * n + 0.5, the usual opengl convention.
+    // strength 0 leaves the normal alone, 1 applies the map fully
+    Normal { map: Rc<dyn Texture>, strength: f64 },
+    // grayscale height (red channel) times scale, in world units
+    Bump { map: Rc<dyn Texture>, scale: f64 },
+}
+
+// wraps a material, replacing its shading normal with one from a normal or
+// bump map. the geometric normal still decides front_face and where
+// scattered rays start
+pub struct NormalMapped {
+    inner: Rc<dyn Material>,
+    map: SurfaceMap,
+}
+
+impl NormalMapped {
+    pub fn new(inner: Rc<dyn Material>, map: SurfaceMap) -> Self {
+        Self { inner, map }
+    }
+
+    fn height(map: &Rc<dyn Texture>, scale: f64, u: f64, v: f64, p: &Vec3) -> f64 {
+        map.value(u, v, p).x() * scale
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        self.inner.scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        // without a parameterization there is no tangent frame to map in
+        if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+            return self.inner.shading_normal(rec);
+        }
+
+        let n = self.inner.shading_normal(rec);
+        let mapped = match &self.map {
+            SurfaceMap::Normal { map, strength } => {
+                let c = map.value(rec.u, rec.v, &rec.p);
+                let t = Vec3::new((2.0 * c.x() - 1.0) * strength, (2.0 * c.y() - 1.0) * strength, 2.0 * c.z() - 1.0);
+
+                // frame from the outward side so maps look the same from
+                // behind, with the bitangent along dpdv
+                let outward = if rec.front_face { n } else { -n };
+                let tangent = (rec.dpdu - outward * outward.dot(&rec.dpdu)).unit_vector();
+                let mut bitangent = outward.cross(&tangent);
+                if bitangent.dot(&rec.dpdv) < 0.0 { bitangent = -bitangent; }
+
+                t.x() * tangent + t.y() * bitangent + t.z() * outward
+            }
+            SurfaceMap::Bump { map, scale } => {
+                // forward differences of the displaced surface p + h(u, v) n,
+                // half a texel apart for images
+                let (du, dv) = match map.resolution() {
+                    Some((width, height)) => (0.5 / width.max(1) as f64, 0.5 / height.max(1) as f64),
+                    None => (BUMP_STEP / rec.dpdu.length(), BUMP_STEP / rec.dpdv.length()),
+                };
+                let h = Self::height(map, *scale, rec.u, rec.v, &rec.p);
+                let h_u = Self::height(map, *scale, rec.u + du, rec.v, &(rec.p + rec.dpdu * du));
+                let h_v = Self::height(map, *scale, rec.u, rec.v + dv, &(rec.p + rec.dpdv * dv));
+
+                let outward = if rec.front_face { n } else { -n };
+                let dpdu = rec.dpdu + outward * ((h_u - h) / du);
+                let dpdv = rec.dpdv + outward * ((h_v - h) / dv);
+                let bumped = dpdu.cross(&dpdv);
+                if bumped.dot(&outward) < 0.0 { -bumped } else { bumped }
+            }
+        };
+
+        if mapped.near_zero() { return n; }
+        let mapped = mapped.unit_vector();
+        // back to facing the ray like the geometric normal
+        if mapped.dot(&rec.geometric_normal) < 0.0 { -mapped } else { mapped }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.inner.albedo(rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.inner.emitted(r_in, rec)
+    }
+
+    fn places_scattered(&self, rec: &HitRecord) -> bool {
+        self.inner.places_scattered(rec)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"normal_mapped");
+        self.inner.hash_into(state);
+        let (tag, map, amount): (&[u8], _, _) = match &self.map {
+            SurfaceMap::Normal { map, strength } => (b"normal", map, strength),
+            SurfaceMap::Bump { map, scale } => (b"bump", map, scale),
+        };
+        state.write(tag);
+        map.hash_into(state);
+        state.write_u64(amount.to_bits());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    // height u, optionally claiming to be an image
+    struct Ramp(Option<(usize, usize)>);
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: &Vec3) -> Color {
+            Color::new(u, u, u)
+        }
+
+        fn resolution(&self) -> Option<(usize, usize)> {
+            self.0
+        }
+
+        fn hash_into(&self, state: &mut dyn Hasher) {
+            state.write(b"ramp");
+        }
+    }
+
+    #[test]
+    fn bump_slope_does_not_depend_on_the_step() {
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 1.0),
+            dpdu: Vec3::new(2.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 2.0, 0.0),
+            u: 0.5,
+            v: 0.5,
+            front_face: true,
+            ..HitRecord::default()
+        };
+        let inner: Rc<dyn Material> = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+
+        // h = 0.5 u over dp/du = 2 is a slope of 1/4 along x
+        let expected = Vec3::new(-0.25, 0.0, 1.0).unit_vector();
+        for resolution in [None, Some((4, 4)), Some((4096, 1))] {
+            let map = SurfaceMap::Bump { map: Rc::new(Ramp(resolution)), scale: 0.5 };
+            let n = NormalMapped::new(inner.clone(), map).shading_normal(&rec);
+            assert!((n - expected).length() < 1e-9, "{:?}: {:?}", resolution, n);
+        }
+    }
+}
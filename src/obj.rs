@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::material::Material;
+use crate::mesh::{Face, Mesh, MeshMaterial};
+use crate::mtl;
+use crate::vec3::Vec3;
+
+// geometry of a wavefront .obj file before materials are attached
+#[derive(Default)]
+struct Model {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<Face>,
+    // usemtl names the faces' material indices refer to (None before the
+    // first usemtl), in the order they are first used
+    materials: Vec<Option<String>>,
+    libraries: Vec<String>,
+}
+
+// wavefront .obj model as one mesh with a material index per face.
+// polygons are split into triangle fans, materials come from the model's
+// mtllib files (see mtl.rs) and faces without one get default_mat
+pub fn load(path: &str, default_mat: Rc<dyn Material>) -> Result<Mesh, Error> {
+    let model = parse(&fs::read_to_string(path)?, path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    if model.faces.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{}: no faces", path)));
+    }
+
+    let mut library: HashMap<String, MeshMaterial> = HashMap::new();
+    for file in &model.libraries {
+        library.extend(mtl::load(&dir.join(file).to_string_lossy())?);
+    }
+
+    let materials = model.materials.iter().map(|name| match name {
+        Some(name) => library.get(name).cloned().ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("{}: unknown material {}", path, name))
+        }),
+        None => Ok(MeshMaterial::new(default_mat.clone())),
+    }).collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Mesh::new(model.positions, model.normals, model.uvs, &model.faces, materials))
+}
+
+// path is only used in error messages
+fn parse(source: &str, path: &str) -> Result<Model, Error> {
+    let mut model = Model::default();
+    let mut current: Option<String> = None;
+
+    for (line_no, line) in source.lines().enumerate() {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path, line_no + 1, msg));
+
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else { continue; };
+        if keyword.starts_with('#') { continue; }
+        let args: Vec<&str> = words.collect();
+
+        let numbers = || -> Result<Vec<f64>, Error> {
+            args.iter().map(|a| a.parse().map_err(|_| invalid("expected a number"))).collect()
+        };
+
+        match keyword {
+            "v" => match numbers()?.as_slice() {
+                [x, y, z, ..] => model.positions.push(Vec3::new(*x, *y, *z)),
+                _ => return Err(invalid("vertex needs x y z")),
+            },
+            "vn" => match numbers()?.as_slice() {
+                [x, y, z] => model.normals.push(Vec3::new(*x, *y, *z)),
+                _ => return Err(invalid("normal needs x y z")),
+            },
+            "vt" => match numbers()?.as_slice() {
+                [u] => model.uvs.push((*u, 0.0)),
+                [u, v, ..] => model.uvs.push((*u, *v)),
+                _ => return Err(invalid("texture coordinate needs u [v]")),
+            },
+            "f" => {
+                if args.len() < 3 { return Err(invalid("face needs at least three vertices")); }
+                let corners = args.iter()
+                    .map(|a| parse_corner(a, &model).ok_or_else(|| invalid(&format!("invalid face vertex {}", a))))
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                let material = match model.materials.iter().position(|n| *n == current) {
+                    Some(i) => i,
+                    None => {
+                        model.materials.push(current.clone());
+                        model.materials.len() - 1
+                    }
+                };
+                for i in 1..corners.len() - 1 {
+                    let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+                    let face = Face {
+                        positions: [a.0, b.0, c.0],
+                        uvs: a.1.zip(b.1).zip(c.1).map(|((a, b), c)| [a, b, c]),
+                        normals: a.2.zip(b.2).zip(c.2).map(|((a, b), c)| [a, b, c]),
+                        material,
+                    };
+                    model.faces.push(face);
+                }
+            }
+            "usemtl" => current = Some(args.first().ok_or_else(|| invalid("usemtl without a name"))?.to_string()),
+            "mtllib" => model.libraries.extend(args.iter().map(|a| a.to_string())),
+            // objects, groups and smoothing groups don't change the result
+            _ => {}
+        }
+    }
+
+    Ok(model)
+}
+
+// one v, v/vt, v//vn or v/vt/vn corner of a face as 0-based indices.
+// negative indices count back from the last element read so far
+fn parse_corner(spec: &str, model: &Model) -> Option<(usize, Option<usize>, Option<usize>)> {
+    let index = |s: &str, len: usize| -> Option<usize> {
+        let i: i64 = s.parse().ok()?;
+        let i = if i < 0 { len as i64 + i } else { i - 1 };
+        if 0 <= i && i < len as i64 { Some(i as usize) } else { None }
+    };
+
+    let mut parts = spec.split('/');
+    let v = index(parts.next()?, model.positions.len())?;
+    let vt = match parts.next() {
+        None | Some("") => None,
+        Some(s) => Some(index(s, model.uvs.len())?),
+    };
+    let vn = match parts.next() {
+        None | Some("") => None,
+        Some(s) => Some(index(s, model.normals.len())?),
+    };
+    if parts.next().is_some() { return None; }
+
+    Some((v, vt, vn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    #[test]
+    fn polygons_are_fanned() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n", "quad.obj").unwrap();
+        assert_eq!(model.positions.len(), 4);
+        let faces = &model.faces;
+        assert_eq!(faces.len(), 2);
+        assert_eq!(faces[0].positions, [0, 1, 2]);
+        assert_eq!(faces[1].positions, [0, 2, 3]);
+        assert!(faces[0].uvs.is_none() && faces[0].normals.is_none());
+    }
+
+    #[test]
+    fn corner_formats_and_negative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n\
+                      f 1/1/1 2/2/1 3/3/1\nf -3//-1 -2//-1 -1//-1\nf 1/1 2/2 3/3\n";
+        let model = parse(source, "tri.obj").unwrap();
+        let faces = &model.faces;
+        assert_eq!(faces.len(), 3);
+        assert_eq!(faces[0].uvs, Some([0, 1, 2]));
+        assert_eq!(faces[0].normals, Some([0, 0, 0]));
+        assert_eq!(faces[1].positions, [0, 1, 2]);
+        assert_eq!(faces[1].uvs, None);
+        assert_eq!(faces[1].normals, Some([0, 0, 0]));
+        assert_eq!(faces[2].uvs, Some([0, 1, 2]));
+        assert_eq!(faces[2].normals, None);
+    }
+
+    #[test]
+    fn faces_get_material_indices() {
+        let source = "mtllib a.mtl b.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                      f 1 2 3\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\nusemtl red\nf 3 2 1\n";
+        let model = parse(source, "groups.obj").unwrap();
+        assert_eq!(model.libraries, ["a.mtl", "b.mtl"]);
+        assert_eq!(model.materials, [None, Some("red".to_string()), Some("blue".to_string())]);
+        let indices: Vec<usize> = model.faces.iter().map(|f| f.material).collect();
+        assert_eq!(indices, [0, 1, 2, 1]);
+
+        // usemtl without faces does not add a slot
+        let model = parse("v 0 0 0
+v 1 0 0
+v 0 1 0
+usemtl unused
+usemtl red
+f 1 2 3
+", "unused.obj").unwrap();
+        assert_eq!(model.materials, [Some("red".to_string())]);
+    }
+
+    #[test]
+    fn load_uses_the_material_library() {
+        let dir = std::env::temp_dir().join(format!("rt-obj-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("two.mtl"), "newmtl red\nKd 1 0 0\nback blue\nnewmtl gold\nKd 1 0.8 0.3\nPm 1\nPr 0.2\n\
+                                        newmtl blue\nKd 0 0 1\n").unwrap();
+        fs::write(dir.join("two.obj"), "mtllib two.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+                                        usemtl red\nf 1 2 3\nusemtl gold\nf 2 4 3\n").unwrap();
+        fs::write(dir.join("missing.obj"), "mtllib two.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl green\nf 1 2 3\n").unwrap();
+
+        let default_mat: Rc<dyn Material> = Rc::new(crate::material::Lambertian::new(&crate::color::Color::new(0.5, 0.5, 0.5)));
+        let mesh = load(&dir.join("two.obj").to_string_lossy(), default_mat.clone()).unwrap();
+        let albedo = |x: f64, z: f64| {
+            let r = Ray::with_time(Vec3::new(x, x, z), Vec3::new(0.0, 0.0, -z), 0.0);
+            let mut rec = HitRecord::default();
+            assert!(mesh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec));
+            rec.mat.albedo(&rec)
+        };
+        // one mesh, red in the lower left triangle and gold in the other.
+        // red is two-sided with blue on the back
+        assert_eq!((albedo(0.25, 1.0).x(), albedo(0.25, 1.0).z()), (1.0, 0.0));
+        assert_eq!((albedo(0.25, -1.0).x(), albedo(0.25, -1.0).z()), (0.0, 1.0));
+        assert_eq!(albedo(0.75, 1.0).y(), 0.8);
+        assert!(load(&dir.join("missing.obj").to_string_lossy(), default_mat).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opacity_maps_cut_holes() {
+        let dir = std::env::temp_dir().join(format!("rt-obj-mask-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // left half transparent, right half opaque
+        fs::write(dir.join("mask.ppm"), "P3\n2 1\n255\n0 0 0 255 255 255\n").unwrap();
+        fs::write(dir.join("leaf.mtl"), "newmtl leaf\nKd 0 1 0\nmap_d mask.ppm\n").unwrap();
+        fs::write(dir.join("leaf.obj"), "mtllib leaf.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                                         vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nusemtl leaf\nf 1/1 2/2 3/3 4/4\n").unwrap();
+
+        let default_mat: Rc<dyn Material> = Rc::new(crate::material::Lambertian::new(&crate::color::Color::new(0.5, 0.5, 0.5)));
+        let meshes = load(&dir.join("leaf.obj").to_string_lossy(), default_mat).unwrap();
+        let hits = |x: f64| {
+            let r = Ray::with_time(Vec3::new(x, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            meshes.hit(&r, Interval::new(0.001, f64::INFINITY), &mut HitRecord::default())
+        };
+        assert!(!hits(0.25));
+        assert!(hits(0.75));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_faces_are_rejected() {
+        for source in ["v 0 0 0\nv 1 0 0\nf 1 2\n", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n",
+                       "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n",
+                       "v 0 0\n"] {
+            assert!(parse(source, "bad.obj").is_err(), "{:?}", source);
+        }
+    }
+}
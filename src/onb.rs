@@ -0,0 +1,38 @@
+use crate::vec3::Vec3;
+
+// orthonormal basis, w is the surface normal
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_normal(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).unit_vector();
+        let u = v.cross(&w);
+        Self { u, v, w }
+    }
+
+    // u follows the tangent as closely as possible
+    pub fn from_normal_tangent(n: &Vec3, tangent: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = *tangent - w * w.dot(tangent);
+        if u.near_zero() {
+            return Self::from_normal(n);
+        }
+        let u = u.unit_vector();
+        let v = w.cross(&u);
+        Self { u, v, w }
+    }
+
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
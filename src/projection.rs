@@ -0,0 +1,104 @@
+use crate::libs::*;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FisheyeModel {
+    // image radius proportional to the angle from the axis
+    Equidistant,
+    // equal-area, r = 2 sin(theta / 2)
+    Equisolid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubeFace {
+    PosX, NegX, PosY, NegY, PosZ, NegZ,
+}
+
+impl CubeFace {
+    // (forward, up) of a camera rendering this face
+    pub fn orientation(&self) -> (Vec3, Vec3) {
+        match self {
+            CubeFace::PosX => (Vec3::new( 1.0, 0.0, 0.0), Vec3::new(0.0, 1.0,  0.0)),
+            CubeFace::NegX => (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0,  0.0)),
+            CubeFace::PosY => (Vec3::new(0.0,  1.0, 0.0), Vec3::new(0.0, 0.0,  1.0)),
+            CubeFace::NegY => (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+            CubeFace::PosZ => (Vec3::new(0.0, 0.0,  1.0), Vec3::new(0.0, 1.0,  0.0)),
+            CubeFace::NegZ => (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0,  0.0)),
+        }
+    }
+}
+
+// how pixels map to ray directions. everything except Perspective ignores
+// defocus blur
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Projection {
+    // pinhole / thin lens from vfov
+    #[default]
+    Perspective,
+    // parallel rays through a viewport of this height in world units
+    Orthographic(f64),
+    // full 360 x 180 degree panorama
+    Equirectangular,
+    // circular image covering fov degrees
+    Fisheye(f64, FisheyeModel),
+    // one 90 degree face of a cube map around lookfrom, ignores lookat / vup
+    Cube(CubeFace),
+}
+
+impl Projection {
+    // perspective | orthographic:<height> | equirectangular |
+    // fisheye:<fov>[:equisolid] | cube:<+x|-x|+y|-y|+z|-z>
+    pub fn parse(spec: &str) -> Option<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        match parts.as_slice() {
+            ["perspective"] => Some(Projection::Perspective),
+            ["orthographic", height] => height.parse().ok().map(Projection::Orthographic),
+            ["equirectangular"] => Some(Projection::Equirectangular),
+            ["fisheye", fov] => fov.parse().ok().map(|fov| Projection::Fisheye(fov, FisheyeModel::Equidistant)),
+            ["fisheye", fov, "equidistant"] => fov.parse().ok().map(|fov| Projection::Fisheye(fov, FisheyeModel::Equidistant)),
+            ["fisheye", fov, "equisolid"] => fov.parse().ok().map(|fov| Projection::Fisheye(fov, FisheyeModel::Equisolid)),
+            ["cube", face] => {
+                let face = match *face {
+                    "+x" => CubeFace::PosX, "-x" => CubeFace::NegX,
+                    "+y" => CubeFace::PosY, "-y" => CubeFace::NegY,
+                    "+z" => CubeFace::PosZ, "-z" => CubeFace::NegZ,
+                    _ => return None,
+                };
+                Some(Projection::Cube(face))
+            }
+            _ => None,
+        }
+    }
+
+    // direction in camera space (x right, y up, looking down -z) for
+    // normalized image coordinates s, t in [0, 1] from the upper left.
+    // None where the projection does not cover the image
+    pub fn camera_direction(&self, s: f64, t: f64, aspect: f64) -> Option<Vec3> {
+        match self {
+            Projection::Equirectangular => {
+                let phi = (s - 0.5) * 2.0 * PI;
+                let theta = (0.5 - t) * PI;
+                Some(Vec3::new(theta.cos() * phi.sin(), theta.sin(), -theta.cos() * phi.cos()))
+            }
+            Projection::Fisheye(fov, model) => {
+                // unit circle fits the shorter image side
+                let (mut x, mut y) = (2.0*s - 1.0, 1.0 - 2.0*t);
+                if aspect >= 1.0 { x *= aspect; } else { y /= aspect; }
+
+                let r = f64::sqrt(x*x + y*y);
+                if r > 1.0 { return None; }
+
+                let half_fov = deg_to_rad(*fov) / 2.0;
+                let theta = match model {
+                    FisheyeModel::Equidistant => r * half_fov,
+                    FisheyeModel::Equisolid => 2.0 * f64::asin(r * f64::sin(half_fov / 2.0)),
+                };
+
+                let (dx, dy) = if r > 0.0 { (x / r, y / r) } else { (0.0, 0.0) };
+                Some(Vec3::new(theta.sin() * dx, theta.sin() * dy, -theta.cos()))
+            }
+            // planar projections are handled with the viewport in Camera
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::hash::Hasher;
 use std::rc::Rc;
 
 use crate::hittable::*;
@@ -6,6 +7,9 @@ use crate::aabb::AABB;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::interval::Interval;
+use crate::stats;
+use crate::libs::next_object_id;
+use crate::texture::AlphaMask;
 
 pub struct Quad {
     Q: Vec3,
@@ -16,6 +20,8 @@ pub struct Quad {
     bbox: AABB,
     normal: Vec3,
     D: f64,
+    id: u32,
+    mask: Option<AlphaMask>,
 }
 
 impl Quad {
@@ -26,9 +32,15 @@ impl Quad {
         let n = u.cross(v);
         let normal = n.unit_vector();
         let D = normal.dot(Q);
-        let mut w = n / n.dot(&n);
+        let w = n / n.dot(&n);
         
-        Self { Q: *Q, u: *u, v: *v, w, mat, bbox, normal, D }
+        Self { Q: *Q, u: *u, v: *v, w, mat, bbox, normal, D, id: next_object_id(), mask: None }
+    }
+
+    // cut holes where the mask is transparent, e.g. leaves or a fence
+    pub fn with_mask(mut self, mask: AlphaMask) -> Self {
+        self.mask = Some(mask);
+        self
     }
 
     fn compute_bounding_box(Q: &Vec3, u: &Vec3, v: &Vec3) -> AABB {
@@ -55,6 +67,7 @@ impl Hittable for Quad {
     }
 
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::count_primitive_test();
         let denom = self.normal.dot(&r.direction());
 
         // ray does not hit if parallel to plane
@@ -74,11 +87,31 @@ impl Hittable for Quad {
         // ray outside quad
         if !Self::is_interior(alpha, beta) { return false; }
 
+        // ray passes through a cutout
+        if let Some(mask) = &self.mask {
+            if !mask.opaque(alpha, beta, &intersection) { return false; }
+        }
+
         rec.t = t;
         rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.dpdu = self.u;
+        rec.dpdv = self.v;
         rec.mat = self.mat.clone();
+        rec.object_id = self.id;
         rec.set_face_normal(r, &self.normal);
 
+        stats::count_hit("quad");
         return true;
     }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"quad");
+        for v in [self.Q, self.u, self.v] {
+            v.hash_into(state);
+        }
+        self.mat.hash_into(state);
+        if let Some(mask) = &self.mask { mask.hash_into(state); }
+    }
 }
\ No newline at end of file
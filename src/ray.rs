@@ -4,11 +4,15 @@ use super::vec3::Vec3;
 pub struct Ray {
     orig: Vec3,
     dir: Vec3,
+    // seconds, objects are posed for this instant
+    tm: f64,
+    // nm, only set in spectral mode
+    lambda: Option<f64>,
 }
 
 impl Ray {
-    pub fn new(orig: Vec3, dir: Vec3) -> Self {
-        Self { orig, dir }
+    pub fn with_time(orig: Vec3, dir: Vec3, tm: f64) -> Self {
+        Self { orig, dir, tm, lambda: None }
     }
 
     pub fn origin(&self) -> Vec3 {
@@ -18,6 +22,25 @@ impl Ray {
         self.dir
     }
 
+    pub fn with_wavelength(mut self, lambda: f64) -> Self {
+        self.lambda = Some(lambda);
+        self
+    }
+
+    // new ray that carries over this one's time and wavelength, for
+    // scattered / transformed rays
+    pub fn spawn(&self, orig: Vec3, dir: Vec3) -> Self {
+        Self { orig, dir, tm: self.tm, lambda: self.lambda }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.tm
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.lambda
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.orig + (self.dir * t)
     }
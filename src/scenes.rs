@@ -0,0 +1,751 @@
+use std::hash::Hasher;
+use std::io::{Error, ErrorKind};
+use std::rc::Rc;
+
+use crate::animated::{Animated, TransformKey};
+use crate::animation::{CameraAnimation, Keyframe, Interpolation};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::libs::*;
+use crate::material::*;
+use crate::mesh::Mesh;
+use crate::microfacet::*;
+use crate::obj;
+use crate::texture::{self, AlphaMask, CheckerTexture, LuminanceAlpha, WaveTexture};
+use crate::spectrum::Ior;
+use crate::normal_map::{NormalMapped, SurfaceMap};
+use crate::quad::Quad;
+use crate::sphere::Sphere;
+use crate::subsurface::{Medium, Subsurface};
+use crate::thin_film::ThinFilm;
+use crate::vec3::Vec3;
+
+pub struct Scene {
+    pub name: String,
+    pub world: HittableList,
+    pub cam: Camera,
+    // camera path for image sequences, if the scene has one
+    pub animation: Option<CameraAnimation>,
+}
+
+impl Scene {
+    // scenes are looked up by name so other processes (resumed renders,
+    // render workers) can rebuild exactly the same one. model:<file.obj>
+    // shows a model on a floor
+    pub fn load(name: &str) -> Result<Self, Error> {
+        reset_object_ids();
+
+        if let Some(path) = name.strip_prefix("model:") {
+            let (world, cam) = model(path)?;
+            return Ok(Self { name: name.to_string(), world, cam, animation: None });
+        }
+
+        let build = match name {
+            "quads"            => quads,
+            "bouncing-spheres" => bouncing_spheres,
+            "glass-spheres"    => glass_spheres,
+            "moving-objects"   => moving_objects,
+            "materials"        => materials,
+            "frosted-glass"    => frosted_glass,
+            "principled"       => principled,
+            "dispersion"       => dispersion,
+            "bump-map"         => bump_map,
+            "cutouts"          => cutouts,
+            "two-sided"        => two_sided,
+            "subsurface"       => subsurface,
+            "thin-film"        => thin_film,
+            _ => {
+                let msg = format!("unknown scene: {} (available: {}, model:<file.obj>)", name, Self::names().join(", "));
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+
+        // random scenes have to come out the same every time
+        let mut hasher = Fnv1a::default();
+        hasher.write(name.as_bytes());
+        seed_rng(hasher.finish());
+
+        let (world, cam) = build();
+        seed_rng(rand::random());
+
+        let animation = match name {
+            "bouncing-spheres" => Some(bouncing_spheres_flythrough(&cam)),
+            // camera stays put, the objects move
+            "moving-objects"   => {
+                let mut animation = CameraAnimation::default();
+                animation.add(Keyframe::from_camera(0.0, &cam));
+                animation.add(Keyframe::from_camera(2.0, &cam));
+                Some(animation)
+            }
+            _ => None,
+        };
+
+        Ok(Self { name: name.to_string(), world, cam, animation })
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &["quads", "bouncing-spheres", "glass-spheres", "moving-objects", "materials", "frosted-glass", "principled", "dispersion", "bump-map", "cutouts", "two-sided", "subsurface", "thin-film"]
+    }
+
+    // identifies the scene's contents together with every camera setting
+    // that changes the rendered image
+    pub fn hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        hasher.write(self.name.as_bytes());
+        self.world.hash_into(&mut hasher);
+        self.cam.hash_settings(&mut hasher);
+        hasher.finish()
+    }
+}
+
+fn quads() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let left_red =     Rc::new(Lambertian::new(&Color::new(1.0, 0.2, 0.2)));
+    let back_green =   Rc::new(Lambertian::new(&Color::new(0.2, 1.0, 0.2)));
+    let right_blue =   Rc::new(Lambertian::new(&Color::new(0.2, 0.2, 1.0)));
+    let upper_orange = Rc::new(Lambertian::new(&Color::new(1.0, 0.5, 0.0)));
+    let lower_teal =   Rc::new(Lambertian::new(&Color::new(0.2, 0.8, 0.8)));
+
+    world.add(Rc::new(Quad::new(&Vec3::new(-3.0, -2.0, 5.0),
+                                &Vec3::new(0.0, 0.0, -4.0),
+                                &Vec3::new(0.0, 4.0, 0.0),
+                                left_red)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, -2.0, 0.0),
+                                &Vec3::new(4.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 4.0, 0.0),
+                                back_green)));
+    world.add(Rc::new(Quad::new(&Vec3::new(3.0, -2.0, 1.0),
+                                &Vec3::new(0.0, 0.0, 4.0),
+                                &Vec3::new(0.0, 4.0, 0.0),
+                                right_blue)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, 3.0, 1.0),
+                                &Vec3::new(4.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 4.0),
+                                upper_orange)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-2.0, -3.0, 5.0),
+                                &Vec3::new(4.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, -4.0),
+                                lower_teal)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 1.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 80.0;
+    cam.lookfrom = Vec3::new(0.0, 0.0, 9.0);
+    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    (world, cam)
+}
+
+fn bouncing_spheres() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground_mat = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat)));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let mat = rand_double();
+            let center = Vec3::new(a as f64 + 0.9*rand_double(), 0.2, b as f64 + 0.9*rand_double());
+
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Rc<dyn Material>;
+
+                if mat < 0.8 {
+                    let albedo = Color::random() * Color::random();
+                    sphere_material = Rc::new(Lambertian::new(&albedo));
+                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
+                } else if mat < 0.95 {
+                    let albedo = Color::random_bounded(0.5, 1.0);
+                    let fuzz = rand_range(0.0, 0.5);
+                    sphere_material = Rc::new(Metal::new(&albedo, fuzz));
+                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
+                } else {
+                    sphere_material = Rc::new(Dielectric::new(1.333, 0.0));
+                    world.add(Rc::new(Sphere::new(&center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+
+    let mat1 = Rc::new(Dielectric::new(1.333, 0.1));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 1.0, 0.0), 1.0, mat1)));
+
+    let mat2 = Rc::new(Dielectric::new(1.333, 0.2));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-4.0, 1.0, 0.0), 1.0, mat2)));
+
+    let mat3 = Rc::new(Dielectric::new(1.333, 0.01));
+    world.add(Rc::new(Sphere::new(&Vec3::new(4.0, 1.0, 0.0), 1.0, mat3)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 32;
+    cam.max_depth = 8;
+
+    cam.vfov = 20.0;
+    cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
+    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+
+    (world, cam)
+}
+
+// swoop down between the small spheres, pulling focus onto the big ones
+fn bouncing_spheres_flythrough(cam: &Camera) -> CameraAnimation {
+    let mut animation = CameraAnimation::default();
+    animation.interpolation = Interpolation::Spline;
+
+    animation.add(Keyframe::from_camera(0.0, cam));
+    animation.add(Keyframe {
+        time: 2.0,
+        lookfrom: Vec3::new(9.0, 1.2, 5.0),
+        lookat: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 25.0,
+        focus_dist: 10.3,
+        defocus_angle: 0.8,
+    });
+    animation.add(Keyframe {
+        time: 4.0,
+        lookfrom: Vec3::new(4.0, 0.8, 6.0),
+        lookat: Vec3::new(-4.0, 1.0, 0.0),
+        vfov: 35.0,
+        focus_dist: 10.0,
+        defocus_angle: 1.5,
+    });
+    animation.add(Keyframe {
+        time: 6.0,
+        lookfrom: Vec3::new(-2.0, 3.0, 9.0),
+        lookat: Vec3::new(0.0, 0.5, 0.0),
+        vfov: 30.0,
+        focus_dist: 9.2,
+        defocus_angle: 0.3,
+    });
+
+    animation
+}
+
+fn glass_spheres() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let material_ground = Rc::new(Lambertian::new(&Color::new(1.0, 0.0, 1.0)));
+    let material_left =   Rc::new(Dielectric::new(1.51, 0.0));
+    let material_center = Rc::new(Dielectric::new(1.51, 0.0));
+    let material_right =  Rc::new(Dielectric::new(1.51, 0.0));
+
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 0.0, -1.2), 0.5, material_center)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    (world, cam)
+}
+
+// keyframed objects over two seconds, see --animate
+fn moving_objects() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    // bounces twice, squashing on the ground
+    let ball = Rc::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 1.0, Rc::new(Lambertian::new(&Color::new(0.8, 0.2, 0.1)))));
+    let up = |time, height: f64| TransformKey::new(time).translate(Vec3::new(-2.0, 1.0 + height, 0.0));
+    let squash = |time| TransformKey::new(time).translate(Vec3::new(-2.0, 0.7, 0.0)).scale(Vec3::new(1.2, 0.7, 1.2));
+    world.add(Rc::new(Animated::new(ball, vec![
+        up(0.0, 2.0), squash(0.5), up(1.0, 2.0), squash(1.5), up(2.0, 2.0),
+    ], Interpolation::Linear)));
+
+    // spinning and drifting sign
+    let sign = Rc::new(Quad::new(&Vec3::new(-0.75, -0.75, 0.0),
+                                 &Vec3::new(1.5, 0.0, 0.0),
+                                 &Vec3::new(0.0, 1.5, 0.0),
+                                 Rc::new(Lambertian::new(&Color::new(0.2, 0.4, 0.9)))));
+    world.add(Rc::new(Animated::new(sign, vec![
+        TransformKey::new(0.0).translate(Vec3::new(1.5, 1.5, 0.0)),
+        TransformKey::new(1.0).translate(Vec3::new(2.0, 2.0, 0.5)).rotate(Vec3::new(0.0, 180.0, 20.0)),
+        TransformKey::new(2.0).translate(Vec3::new(1.5, 1.5, 0.0)).rotate(Vec3::new(0.0, 360.0, 0.0)),
+    ], Interpolation::Spline)));
+
+    // static metal sphere for reference
+    let metal = Rc::new(Metal::new(&Color::new(0.8, 0.8, 0.8), 0.05));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 0.7, -2.0), 0.7, metal)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 64;
+    cam.max_depth = 16;
+
+    cam.vfov = 30.0;
+    cam.lookfrom = Vec3::new(0.0, 3.0, 14.0);
+    cam.lookat = Vec3::new(0.0, 1.5, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    // a 1/24s frame with the shutter open half of it
+    cam.shutter = 1.0 / 48.0;
+
+    (world, cam)
+}
+
+// row of microfacet materials going from smooth to rough
+fn materials() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Microfacet::plastic(&Color::new(0.4, 0.4, 0.45), 1.5, 0.6, 0.0));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let front: [Rc<dyn Material>; 4] = [
+        Rc::new(Microfacet::gold(0.1)),
+        Rc::new(Microfacet::copper(0.3)),
+        Rc::new(Microfacet::silver(0.5)),
+        Rc::new(Microfacet::conductor(&Color::new(1.657, 0.880, 0.521), &Color::new(9.224, 6.270, 4.837), 0.5, 0.9)),
+    ];
+    let back: [Rc<dyn Material>; 4] = [
+        Rc::new(Microfacet::plastic(&Color::new(0.8, 0.1, 0.1), 1.5, 0.05, 0.0)),
+        Rc::new(Microfacet::plastic(&Color::new(0.1, 0.6, 0.1), 1.5, 0.3, 0.0)),
+        Rc::new(Microfacet::plastic(&Color::new(0.1, 0.2, 0.8), 1.5, 0.6, 0.0)),
+        Rc::new(Microfacet::plastic(&Color::new(0.9, 0.9, 0.9), 1.5, 1.0, 0.0)),
+    ];
+
+    for (i, mat) in front.into_iter().enumerate() {
+        world.add(Rc::new(Sphere::new(&Vec3::new(-3.3 + 2.2 * i as f64, 1.0, 1.2), 1.0, mat)));
+    }
+    for (i, mat) in back.into_iter().enumerate() {
+        world.add(Rc::new(Sphere::new(&Vec3::new(-3.3 + 2.2 * i as f64, 1.0, -1.2), 1.0, mat)));
+    }
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 30.0;
+    cam.lookfrom = Vec3::new(0.0, 5.0, 13.0);
+    cam.lookat = Vec3::new(0.0, 0.8, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+// glass from clear to frosted in front of a striped wall, plus a thick
+// tinted block
+fn frosted_glass() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Lambertian::new(&Color::new(0.8, 0.8, 0.8)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let stripes = [Color::new(0.9, 0.2, 0.2), Color::new(0.95, 0.95, 0.95), Color::new(0.2, 0.3, 0.9)];
+    for i in 0..12 {
+        let mat = Rc::new(Lambertian::new(&stripes[i % 3]));
+        world.add(Rc::new(Quad::new(&Vec3::new(-6.0 + i as f64, 0.0, -3.0),
+                                    &Vec3::new(1.0, 0.0, 0.0),
+                                    &Vec3::new(0.0, 5.0, 0.0),
+                                    mat)));
+    }
+
+    for (i, roughness) in [0.0, 0.1, 0.3, 0.6].into_iter().enumerate() {
+        let glass = Rc::new(RoughDielectric::new(1.5, roughness));
+        world.add(Rc::new(Sphere::new(&Vec3::new(-3.3 + 2.2 * i as f64, 1.0, 0.0), 1.0, glass)));
+    }
+
+    // deep green where it is thickest
+    let tinted = Rc::new(RoughDielectric::new(1.5, 0.05).with_absorption(&Color::new(0.3, 0.8, 0.5), 1.0));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 0.6, 2.2), 0.6, tinted)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 32;
+
+    cam.vfov = 35.0;
+    cam.lookfrom = Vec3::new(0.0, 2.5, 11.0);
+    cam.lookat = Vec3::new(0.0, 1.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+// one Principled parameter per row, increasing from left to right
+fn principled() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let checker = Rc::new(CheckerTexture::new(1.0, texture::constant(0.2), texture::constant(0.7)));
+    let ground = Rc::new(Principled { base_color: checker, roughness: texture::constant(0.3), ..Principled::default() });
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let rows: [fn(f64) -> Principled; 4] = [
+        |x| Principled { metallic: texture::constant(x), roughness: texture::constant(0.2), ..Principled::new(&Color::new(0.8, 0.3, 0.1)) },
+        |x| Principled { roughness: texture::constant(x), ..Principled::new(&Color::new(0.8, 0.3, 0.1)) },
+        |x| Principled { clearcoat: texture::constant(x), roughness: texture::constant(0.8), ..Principled::new(&Color::new(0.8, 0.3, 0.1)) },
+        |x| Principled { transmission: texture::constant(x), roughness: texture::constant(0.05), ..Principled::new(&Color::new(0.9, 0.95, 1.0)) },
+    ];
+
+    for (row, make) in rows.iter().enumerate() {
+        for col in 0..5 {
+            let x = col as f64 / 4.0;
+            let center = Vec3::new(-4.0 + 2.0 * col as f64, 0.8, -3.0 + 2.0 * row as f64);
+            world.add(Rc::new(Sphere::new(&center, 0.8, Rc::new(make(x)))));
+        }
+    }
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 32.0;
+    cam.lookfrom = Vec3::new(0.0, 9.0, 16.0);
+    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+// constant ior, crown glass, flint glass and diamond in front of a fine
+// checkerboard. the colored fringes only show with --spectral
+fn dispersion() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let checker = Rc::new(CheckerTexture::new(0.25, texture::constant(0.05), texture::constant(0.9)));
+    let wall = Rc::new(Principled { base_color: checker, roughness: texture::constant(1.0), ..Principled::default() });
+    world.add(Rc::new(Quad::new(&Vec3::new(-8.0, -1.0, -3.0),
+                                &Vec3::new(16.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 8.0, 0.0),
+                                wall.clone())));
+    world.add(Rc::new(Quad::new(&Vec3::new(-8.0, -1.0, -3.0),
+                                &Vec3::new(16.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 8.0),
+                                wall)));
+
+    let glasses = [
+        Rc::new(Dielectric::new(1.5, 0.0)),
+        Rc::new(Dielectric::dispersive(Ior::bk7(), 0.0)),
+        Rc::new(Dielectric::dispersive(Ior::sf11(), 0.0)),
+        Rc::new(Dielectric::dispersive(Ior::diamond(), 0.0)),
+    ];
+    for (i, glass) in glasses.into_iter().enumerate() {
+        world.add(Rc::new(Sphere::new(&Vec3::new(-3.3 + 2.2 * i as f64, 0.0, 0.0), 1.0, glass)));
+    }
+    // a water drop in front
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, -0.7, 2.0), 0.3, Rc::new(Dielectric::dispersive(Ior::water(), 0.0)))));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 200;
+    cam.max_depth = 16;
+
+    cam.vfov = 30.0;
+    cam.lookfrom = Vec3::new(0.0, 1.0, 10.0);
+    cam.lookat = Vec3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+// procedural height maps: raised tiles on the floor, a quilted sphere
+// and a dimpled metal one
+fn bump_map() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let tiles = Rc::new(CheckerTexture::new(1.0, texture::constant(0.0), texture::constant(1.0)));
+    let floor = Rc::new(NormalMapped::new(Rc::new(Lambertian::new(&Color::new(0.7, 0.7, 0.65))),
+                                          SurfaceMap::Bump { map: tiles, scale: 0.02 }));
+    world.add(Rc::new(Quad::new(&Vec3::new(-10.0, 0.0, -10.0),
+                                &Vec3::new(20.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 20.0),
+                                floor)));
+
+    let quilt = Rc::new(WaveTexture::new(0.08));
+    let cloth = Rc::new(NormalMapped::new(Rc::new(Principled::new(&Color::new(0.8, 0.2, 0.2))),
+                                          SurfaceMap::Bump { map: quilt, scale: 0.03 }));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-1.3, 1.0, 0.0), 1.0, cloth)));
+
+    let dimples = Rc::new(WaveTexture::new(0.04));
+    let metal = Rc::new(NormalMapped::new(Rc::new(Microfacet::gold(0.15)),
+                                          SurfaceMap::Bump { map: dimples, scale: 0.01 }));
+    world.add(Rc::new(Sphere::new(&Vec3::new(1.3, 1.0, 0.0), 1.0, metal)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 35.0;
+    cam.lookfrom = Vec3::new(0.0, 3.0, 7.0);
+    cam.lookat = Vec3::new(0.0, 0.8, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+fn cutouts() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Lambertian::new(&Color::new(0.5, 0.6, 0.4)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-10.0, 0.0, -10.0),
+                                &Vec3::new(20.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 20.0),
+                                ground)));
+
+    // lattice fence, a single quad with every other checker cell cut out
+    let lattice = Rc::new(LuminanceAlpha::new(Rc::new(CheckerTexture::new(0.25, texture::constant(0.0), texture::constant(1.0)))));
+    let wood = Rc::new(Lambertian::new(&Color::new(0.55, 0.35, 0.2)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-3.0, 0.0, -1.1),
+                                &Vec3::new(6.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 2.0, 0.0),
+                                wood)
+                      .with_mask(AlphaMask::new(lattice, 0.5))));
+
+    // perforated shell, the inside shows through the holes
+    let holes = Rc::new(LuminanceAlpha::new(Rc::new(WaveTexture::new(0.12))));
+    let shell = Rc::new(Microfacet::copper(0.3));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-1.0, 0.8, 0.5), 0.8, shell)
+                      .with_mask(AlphaMask::new(holes, 0.35))));
+
+    let glass = Rc::new(Dielectric::new(1.5, 0.0));
+    world.add(Rc::new(Sphere::new(&Vec3::new(1.1, 0.6, 0.6), 0.6, glass)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 35.0;
+    cam.lookfrom = Vec3::new(0.0, 2.0, 7.0);
+    cam.lookat = Vec3::new(0.0, 0.8, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+fn two_sided() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-10.0, 0.0, -10.0),
+                                &Vec3::new(20.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 20.0),
+                                ground)));
+
+    // panel light tilted down, it only shines from its front face
+    let light = Rc::new(TwoSided::new(Rc::new(DiffuseLight::new(&Color::new(6.0, 5.0, 4.0))),
+                                      Rc::new(Lambertian::new(&Color::new(0.1, 0.1, 0.1)))));
+    world.add(Rc::new(Quad::new(&Vec3::new(-2.8, 1.6, -0.6),
+                                &Vec3::new(1.4, 0.0, 0.0),
+                                &Vec3::new(0.0, -0.6, 0.8),
+                                light)));
+
+    // folded card, printed on the front and plain cream on the back. the
+    // right leaf faces away so its back shows
+    let print = Rc::new(CheckerTexture::new(0.15, texture::solid(&Color::new(0.8, 0.1, 0.1)), texture::constant(0.9)));
+    let card = Rc::new(TwoSided::new(Rc::new(Principled { base_color: print, ..Principled::default() }),
+                                     Rc::new(Lambertian::new(&Color::new(0.9, 0.85, 0.7)))));
+    world.add(Rc::new(Quad::new(&Vec3::new(0.2, 0.0, 0.6), &Vec3::new(0.6, 0.0, -0.8), &Vec3::new(0.0, 1.2, 0.0), card.clone())));
+    world.add(Rc::new(Quad::new(&Vec3::new(0.8, 0.0, -0.2), &Vec3::new(0.0, 1.2, 0.0), &Vec3::new(0.7, 0.0, 0.7), card)));
+
+    // the inside of a perforated shell is painted blue
+    let holes = Rc::new(LuminanceAlpha::new(Rc::new(WaveTexture::new(0.12))));
+    let shell = Rc::new(TwoSided::new(Rc::new(Microfacet::gold(0.2)),
+                                      Rc::new(Lambertian::new(&Color::new(0.1, 0.3, 0.8)))));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-1.3, 0.7, 0.6), 0.7, shell)
+                      .with_mask(AlphaMask::new(holes, 0.35))));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 35.0;
+    cam.lookfrom = Vec3::new(0.5, 2.5, 6.5);
+    cam.lookat = Vec3::new(-0.2, 0.6, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+fn subsurface() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let ground = Rc::new(Lambertian::new(&Color::new(0.4, 0.4, 0.45)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-10.0, 0.0, -10.0),
+                                &Vec3::new(20.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 20.0),
+                                ground)));
+
+    // skin, marble and wax, with a plain diffuse sphere for comparison
+    let unused = Rc::new(Lambertian::new(&Color::new(0.0, 0.0, 0.0)));
+    let media = [Medium::skin(), Medium::marble(), Medium::wax()];
+    for (i, medium) in media.into_iter().enumerate() {
+        let center = Vec3::new(-2.25 + 1.5 * i as f64, 0.6, 0.0);
+        let boundary = Rc::new(Sphere::new(&center, 0.6, unused.clone()));
+        world.add(Rc::new(Subsurface::new(boundary, medium, 1.4)));
+    }
+    let plaster = Rc::new(Lambertian::new(&Color::new(0.9, 0.85, 0.75)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(2.25, 0.6, 0.0), 0.6, plaster)));
+
+    // a marble block in front, the walk runs inside a triangle mesh
+    let block = Rc::new(Mesh::cube(&Vec3::new(0.0, 0.36, 1.6), 0.7, unused));
+    world.add(Rc::new(Subsurface::new(block, Medium::marble(), 1.5)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 30.0;
+    cam.lookfrom = Vec3::new(0.0, 2.0, 8.0);
+    cam.lookat = Vec3::new(0.0, 0.5, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+fn thin_film() -> (HittableList, Camera) {
+    let mut world = HittableList::default();
+
+    let tiles = Rc::new(CheckerTexture::new(0.5, texture::constant(0.2), texture::constant(0.8)));
+    world.add(Rc::new(Quad::new(&Vec3::new(-10.0, 0.0, -10.0),
+                                &Vec3::new(20.0, 0.0, 0.0),
+                                &Vec3::new(0.0, 0.0, 20.0),
+                                Rc::new(Principled { base_color: tiles, ..Principled::default() }))));
+
+    // heat tinted steel and glass with an anti-reflection style coating
+    let tinted = Rc::new(Metal::new(&Color::new(0.6, 0.6, 0.62), 0.02).with_film(ThinFilm::new(320.0, 2.0)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(-1.6, 0.7, 0.0), 0.7, tinted)));
+
+    let coated = Rc::new(Dielectric::new(1.5, 0.0).with_film(ThinFilm::new(450.0, 1.38)));
+    world.add(Rc::new(Sphere::new(&Vec3::new(1.6, 0.7, 0.0), 0.7, coated)));
+
+    // soap bubbles with swirls of varying film thickness
+    let swirl = Rc::new(WaveTexture::new(0.15));
+    let bubble = Rc::new(SoapBubble::new(ThinFilm::new(400.0, 1.33)).with_thickness_map(swirl, 200.0, 900.0));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 1.0, 0.6), 0.8, bubble.clone())));
+    world.add(Rc::new(Sphere::new(&Vec3::new(0.6, 2.0, -0.8), 0.45, bubble)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 16;
+
+    cam.vfov = 35.0;
+    cam.lookfrom = Vec3::new(0.0, 2.2, 7.0);
+    cam.lookat = Vec3::new(0.0, 1.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+// a model on a gray floor, framed from the front and a little above
+fn model(path: &str) -> Result<(HittableList, Camera), Error> {
+    let mut world = HittableList::default();
+
+    let mesh = obj::load(path, Rc::new(Lambertian::new(&Color::new(0.7, 0.7, 0.7))))?;
+    let bbox = mesh.bounding_box();
+    let center = Vec3::new((bbox.x.min + bbox.x.max) / 2.0, (bbox.y.min + bbox.y.max) / 2.0, (bbox.z.min + bbox.z.max) / 2.0);
+    let radius = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length() / 2.0;
+    let floor_y = bbox.y.min;
+    world.add(Rc::new(mesh));
+
+    let floor = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Quad::new(&Vec3::new(center.x() - 10.0 * radius, floor_y, center.z() - 10.0 * radius),
+                                &Vec3::new(0.0, 0.0, 20.0 * radius),
+                                &Vec3::new(20.0 * radius, 0.0, 0.0),
+                                floor)));
+
+    let mut cam = Camera::default();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.img_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    // far enough back for the bounding sphere to fit the view
+    cam.vfov = 30.0;
+    let distance = 1.1 * radius / f64::sin(deg_to_rad(cam.vfov / 2.0));
+    cam.lookat = center;
+    cam.lookfrom = center + Vec3::new(0.0, 0.35, 1.0).unit_vector() * distance;
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    Ok((world, cam))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::TentFilter;
+
+    #[test]
+    fn hash_is_stable_for_the_same_scene() {
+        assert_eq!(Scene::load("materials").unwrap().hash(), Scene::load("materials").unwrap().hash());
+        assert_ne!(Scene::load("materials").unwrap().hash(), Scene::load("quads").unwrap().hash());
+    }
+
+    #[test]
+    fn hash_covers_contents_and_filter() {
+        let reference = Scene::load("quads").unwrap().hash();
+
+        let mut edited = Scene::load("quads").unwrap();
+        edited.world.add(Rc::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 0.5, Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5))))));
+        assert_ne!(edited.hash(), reference);
+
+        // same radius as the default box filter, different shape
+        let mut filtered = Scene::load("quads").unwrap();
+        filtered.cam.filter = Rc::new(TentFilter::new(0.5));
+        assert_ne!(filtered.hash(), reference);
+    }
+
+    #[test]
+    fn hash_covers_materials() {
+        let hash = |mat: Rc<dyn Material>| {
+            let mut hasher = Fnv1a::default();
+            Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 0.5, mat).hash_into(&mut hasher);
+            hasher.finish()
+        };
+        let red = hash(Rc::new(Lambertian::new(&Color::new(0.8, 0.1, 0.1))));
+        assert_eq!(red, hash(Rc::new(Lambertian::new(&Color::new(0.8, 0.1, 0.1)))));
+        assert_ne!(red, hash(Rc::new(Lambertian::new(&Color::new(0.1, 0.8, 0.1)))));
+        assert_ne!(red, hash(Rc::new(Metal::new(&Color::new(0.8, 0.1, 0.1), 0.0))));
+    }
+}
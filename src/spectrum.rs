@@ -0,0 +1,132 @@
+use std::hash::Hasher;
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::libs::*;
+
+// visible range sampled in spectral mode, nm
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// wavelength used for a dispersive ior in rgb mode
+const LAMBDA_RGB: f64 = 550.0;
+
+pub fn sample_wavelength() -> f64 {
+    rand_range(LAMBDA_MIN, LAMBDA_MAX)
+}
+
+// piecewise gaussian used by the cmf fit
+fn g(lambda: f64, mu: f64, sigma_lo: f64, sigma_hi: f64) -> f64 {
+    let sigma = if lambda < mu { sigma_lo } else { sigma_hi };
+    let t = (lambda - mu) / sigma;
+    f64::exp(-0.5 * t * t)
+}
+
+// CIE 1931 2 degree color matching functions, multi-lobe fit from
+// Wyman, Sloan and Shirley 2013
+pub fn cie_xyz(lambda: f64) -> Color {
+    let x = 1.056 * g(lambda, 599.8, 37.9, 31.0) + 0.362 * g(lambda, 442.0, 16.0, 26.7) - 0.065 * g(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * g(lambda, 568.8, 46.9, 40.5) + 0.286 * g(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * g(lambda, 437.0, 11.8, 36.0) + 0.681 * g(lambda, 459.0, 26.0, 13.8);
+    Color::new(x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(xyz: &Color) -> Color {
+    Color::new( 3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+               -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+                0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z())
+}
+
+// linear srgb of a constant spectrum of 1, used to white balance so that
+// white surfaces under the white sky stay white as in rgb mode
+fn equal_energy_white() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let mut xyz = Color::default();
+        let mut lambda = LAMBDA_MIN;
+        while lambda < LAMBDA_MAX {
+            xyz += cie_xyz(lambda + 0.5);
+            lambda += 1.0;
+        }
+        xyz_to_linear_srgb(&xyz)
+    })
+}
+
+// contribution of one radiance sample at lambda (uniformly sampled) to the
+// pixel's linear srgb color
+pub fn to_linear_srgb(radiance: f64, lambda: f64) -> Color {
+    let xyz = cie_xyz(lambda) * (radiance * (LAMBDA_MAX - LAMBDA_MIN));
+    let rgb = xyz_to_linear_srgb(&xyz);
+    let white = equal_energy_white();
+    Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// smooth reflectance spectrum for an rgb albedo at lambda. the three basis
+// curves sum to one everywhere, so grey stays flat (and white exactly 1)
+// and nothing goes above the brightest channel
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let blue = 1.0 - smoothstep(480.0, 510.0, lambda);
+    let red = smoothstep(570.0, 600.0, lambda);
+    let green = 1.0 - blue - red;
+    rgb.x() * red + rgb.y() * green + rgb.z() * blue
+}
+
+// index of refraction, possibly depending on wavelength
+#[derive(Clone, Copy, Debug)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum b_i lambda^2 / (lambda^2 - c_i), lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    // borosilicate crown glass
+    pub fn bk7() -> Self {
+        Ior::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+
+    // dense flint glass, strong dispersion
+    pub fn sf11() -> Self {
+        Ior::Sellmeier { b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629] }
+    }
+
+    pub fn diamond() -> Self {
+        Ior::Sellmeier { b: [0.3306, 4.3356, 0.0], c: [0.175 * 0.175, 0.106 * 0.106, 0.0] }
+    }
+
+    pub fn water() -> Self {
+        Ior::Cauchy { a: 1.3199, b: 0.00653 }
+    }
+
+    // at lambda nm, or at a representative wavelength in rgb mode
+    pub fn at(&self, lambda: Option<f64>) -> f64 {
+        let l = lambda.unwrap_or(LAMBDA_RGB) / 1000.0;
+        let l2 = l * l;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        let values: &[f64] = match self {
+            Ior::Constant(n) => { state.write(b"constant"); &[*n] }
+            Ior::Cauchy { a, b } => { state.write(b"cauchy"); &[*a, *b] }
+            Ior::Sellmeier { b, c } => { state.write(b"sellmeier"); &[b[0], b[1], b[2], c[0], c[1], c[2]] }
+        };
+        for x in values {
+            state.write_u64(x.to_bits());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::hash::Hasher;
 use std::rc::*;
 
 use crate::hittable::*;
@@ -5,26 +6,58 @@ use crate::ray::Ray;
 use crate::Vec3;
 use crate::interval::*;
 use crate::material::*;
-use crate::color::*;
 use crate::aabb::AABB;
+use crate::stats;
+use crate::libs::{next_object_id, PI};
+use crate::texture::AlphaMask;
 
 pub struct Sphere {
     center: Vec3,
     radius: f64,
     mat: Rc<dyn Material>,
     bbox: AABB,
+    id: u32,
+    mask: Option<AlphaMask>,
 }
 
 impl Sphere {
     pub fn new(center: &Vec3, radius: f64, mat: Rc<dyn Material>) -> Self {
         let rvec = Vec3::new(radius, radius, radius);
         Self { center: *center, radius: radius.max(0.0), mat, 
-               bbox: AABB::from_points(*center-rvec, *center+rvec) }
+               bbox: AABB::from_points(*center-rvec, *center+rvec), id: next_object_id(), mask: None }
+    }
+
+    // cut holes where the mask is transparent, rays can then hit the
+    // inside of the far side
+    pub fn with_mask(mut self, mask: AlphaMask) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    // u goes around the y axis starting from -x, v from the bottom pole up
+    fn sphere_uv(p: &Vec3) -> (f64, f64) {
+        // p is only unit length up to rounding
+        let theta = f64::acos((-p.y()).clamp(-1.0, 1.0));
+        let phi = f64::atan2(-p.z(), p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // dp/du and dp/dv of the parameterization above
+    fn sphere_derivatives(&self, p: &Vec3) -> (Vec3, Vec3) {
+        let theta = f64::acos((-p.y()).clamp(-1.0, 1.0));
+        let phi = f64::atan2(-p.z(), p.x()) + PI;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+
+        let dpdu = Vec3::new(sin_theta * sin_phi, 0.0, sin_theta * cos_phi) * (2.0 * PI * self.radius);
+        let dpdv = Vec3::new(-cos_theta * cos_phi, sin_theta, cos_theta * sin_phi) * (PI * self.radius);
+        (dpdu, dpdv)
     }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::count_primitive_test();
         let oc = self.center - r.origin();
         // quadratic equation
         let r_dir = r.direction();
@@ -39,26 +72,58 @@ impl Hittable for Sphere {
 
         let sqrtd = f64::sqrt(discriminant);
 
-        // find nearest root in acceptable range
+        // nearest root in acceptable range that is not cut away
         let inv_a = 1.0 / a;
-        let mut root = (h - sqrtd) * inv_a;
-        if !(ray_t.surrounds(root)) {
-            root = (h + sqrtd) * inv_a;
+        for root in [(h - sqrtd) * inv_a, (h + sqrtd) * inv_a] {
             if !(ray_t.surrounds(root)) {
-                return false;
+                continue;
+            }
+
+            let p = r.at(root);
+            let outward_normal = (p - self.center) / self.radius;
+            let (u, v) = Self::sphere_uv(&outward_normal);
+            if let Some(mask) = &self.mask {
+                if !mask.opaque(u, v, &p) { continue; }
             }
-        }
 
-        rec.t = root;
-        rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(r, &outward_normal);
-        rec.mat = self.mat.clone();
+            rec.t = root;
+            rec.p = p;
+            rec.set_face_normal(r, &outward_normal);
+            (rec.u, rec.v) = (u, v);
+            (rec.dpdu, rec.dpdv) = self.sphere_derivatives(&outward_normal);
+            rec.mat = self.mat.clone();
+            rec.object_id = self.id;
 
-        return true;
+            stats::count_hit("sphere");
+            return true;
+        }
+
+        return false;
     }
 
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"sphere");
+        self.center.hash_into(state);
+        state.write_u64(self.radius.to_bits());
+        self.mat.hash_into(state);
+        if let Some(mask) = &self.mask { mask.hash_into(state); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uv_at_the_poles_survives_rounding() {
+        for y in [1.0 + 1e-15, -1.0 - 1e-15] {
+            let (u, v) = Sphere::sphere_uv(&Vec3::new(0.0, y, 0.0));
+            assert!(u.is_finite() && v.is_finite());
+        }
+        assert_eq!(Sphere::sphere_uv(&Vec3::new(0.0, -1.0 - 1e-15, 0.0)).1, 0.0);
+    }
 }
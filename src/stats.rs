@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io::{Write, Error};
+use std::time::Duration;
+
+// counters bumped from the hot paths when enabled (--stats). rendering is
+// single threaded so a thread local is enough
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Counters {
+    // every ray traced, including bounces
+    pub rays: u64,
+    pub primary_rays: u64,
+    pub bvh_nodes_visited: u64,
+    pub primitive_tests: u64,
+    // accepted intersections by primitive type
+    // owned names only come from render workers
+    pub hits: BTreeMap<Cow<'static, str>, u64>,
+}
+
+impl Counters {
+    pub fn add(&mut self, other: &Counters) {
+        self.rays += other.rays;
+        self.primary_rays += other.primary_rays;
+        self.bvh_nodes_visited += other.bvh_nodes_visited;
+        self.primitive_tests += other.primitive_tests;
+        for (primitive, count) in &other.hits {
+            *self.hits.entry(primitive.clone()).or_insert(0) += count;
+        }
+    }
+
+    // space separated, for the distributed protocol
+    pub fn to_fields(&self) -> String {
+        let mut fields = format!("{} {} {} {}", self.rays, self.primary_rays, self.bvh_nodes_visited, self.primitive_tests);
+        for (primitive, count) in &self.hits {
+            fields += &format!(" {}={}", primitive, count);
+        }
+        fields
+    }
+
+    pub fn from_fields(fields: &[&str]) -> Option<Self> {
+        let [rays, primary_rays, bvh_nodes_visited, primitive_tests, hits @ ..] = fields else { return None; };
+        let mut c = Counters {
+            rays: rays.parse().ok()?,
+            primary_rays: primary_rays.parse().ok()?,
+            bvh_nodes_visited: bvh_nodes_visited.parse().ok()?,
+            primitive_tests: primitive_tests.parse().ok()?,
+            hits: BTreeMap::new(),
+        };
+        for hit in hits {
+            let (primitive, count) = hit.split_once('=')?;
+            c.hits.insert(Cow::Owned(primitive.to_string()), count.parse().ok()?);
+        }
+        Some(c)
+    }
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static COUNTERS: RefCell<Counters> = RefCell::new(Counters::default());
+}
+
+// counting costs a few percent, so it is off unless asked for
+pub fn enable() {
+    ENABLED.set(true);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get()
+}
+
+pub fn count_ray(primary: bool) {
+    if !enabled() { return; }
+    COUNTERS.with_borrow_mut(|c| {
+        c.rays += 1;
+        if primary { c.primary_rays += 1; }
+    });
+}
+
+pub fn count_bvh_node() {
+    if !enabled() { return; }
+    COUNTERS.with_borrow_mut(|c| c.bvh_nodes_visited += 1);
+}
+
+pub fn count_primitive_test() {
+    if !enabled() { return; }
+    COUNTERS.with_borrow_mut(|c| c.primitive_tests += 1);
+}
+
+pub fn count_hit(primitive: &'static str) {
+    if !enabled() { return; }
+    COUNTERS.with_borrow_mut(|c| *c.hits.entry(Cow::Borrowed(primitive)).or_insert(0) += 1);
+}
+
+pub fn counters() -> Counters {
+    COUNTERS.with_borrow(|c| c.clone())
+}
+
+// counts made elsewhere, i.e. by render workers
+pub fn add(counters: &Counters) {
+    COUNTERS.with_borrow_mut(|c| c.add(counters));
+}
+
+// end of run summary: counters plus wall time of each phase
+#[derive(Default)]
+pub struct Report {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Report {
+    pub fn phase(&mut self, name: &'static str, duration: Duration) {
+        self.phases.push((name, duration));
+    }
+
+    fn phase_time(&self, name: &str) -> Option<Duration> {
+        self.phases.iter().find(|(n, _)| *n == name).map(|(_, d)| *d)
+    }
+
+    fn per(count: u64, total: u64) -> f64 {
+        if total == 0 { 0.0 } else { count as f64 / total as f64 }
+    }
+
+    fn rays_per_second(&self, c: &Counters) -> f64 {
+        match self.phase_time("render") {
+            Some(d) if d.as_secs_f64() > 0.0 => c.rays as f64 / d.as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+
+    // the counters are left out when they weren't enabled
+    pub fn print(&self, out: &mut dyn Write) -> Result<(), Error> {
+        if enabled() {
+            self.print_counters(out)?;
+        }
+        for (name, duration) in &self.phases {
+            writeln!(out, "Time ({}): {}{:?}", name, " ".repeat(13usize.saturating_sub(name.len())), duration)?;
+        }
+        Ok(())
+    }
+
+    fn print_counters(&self, out: &mut dyn Write) -> Result<(), Error> {
+        let c = counters();
+
+        writeln!(out, "Rays:                 {} ({} primary)", c.rays, c.primary_rays)?;
+        writeln!(out, "Rays per second:      {:.0}", self.rays_per_second(&c))?;
+        writeln!(out, "Average path length:  {:.2}", Self::per(c.rays, c.primary_rays))?;
+        writeln!(out, "BVH nodes per ray:    {:.2}", Self::per(c.bvh_nodes_visited, c.rays))?;
+        writeln!(out, "Primitive tests/ray:  {:.2}", Self::per(c.primitive_tests, c.rays))?;
+        for (primitive, count) in &c.hits {
+            writeln!(out, "Hits ({}): {}{}", primitive, " ".repeat(13usize.saturating_sub(primitive.len())), count)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let c = counters();
+
+        let hits: Vec<String> = c.hits.iter().map(|(p, n)| format!("\"{}\": {}", p, n)).collect();
+        let phases: Vec<String> = self.phases.iter().map(|(p, d)| format!("\"{}\": {}", p, d.as_secs_f64())).collect();
+
+        format!("{{\n  \"rays\": {},\n  \"primary_rays\": {},\n  \"rays_per_second\": {},\n  \
+                 \"average_path_length\": {},\n  \"bvh_nodes_visited\": {},\n  \"bvh_nodes_per_ray\": {},\n  \
+                 \"primitive_tests\": {},\n  \"primitive_tests_per_ray\": {},\n  \"hits\": {{{}}},\n  \
+                 \"phase_seconds\": {{{}}}\n}}\n",
+                c.rays, c.primary_rays, self.rays_per_second(&c),
+                Self::per(c.rays, c.primary_rays), c.bvh_nodes_visited, Self::per(c.bvh_nodes_visited, c.rays),
+                c.primitive_tests, Self::per(c.primitive_tests, c.rays), hits.join(", "), phases.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counting_is_off_by_default() {
+        count_ray(true);
+        count_hit("sphere");
+        assert_eq!(counters(), Counters::default());
+
+        enable();
+        count_ray(true);
+        count_ray(false);
+        count_hit("sphere");
+        let c = counters();
+        assert_eq!((c.rays, c.primary_rays), (2, 1));
+        assert_eq!(c.hits["sphere"], 1);
+    }
+
+    #[test]
+    fn worker_counters_round_trip() {
+        let mut c = Counters { rays: 10, primary_rays: 4, bvh_nodes_visited: 30, primitive_tests: 12, hits: BTreeMap::new() };
+        c.hits.insert(Cow::Borrowed("quad"), 3);
+        c.hits.insert(Cow::Borrowed("sphere"), 5);
+
+        let line = c.to_fields();
+        let back = Counters::from_fields(&line.split_whitespace().collect::<Vec<_>>()).unwrap();
+        assert_eq!(back, c);
+        assert!(Counters::from_fields(&["1", "2", "3"]).is_none());
+        assert!(Counters::from_fields(&["1", "2", "3", "4", "quad"]).is_none());
+
+        let mut total = back.clone();
+        total.add(&c);
+        assert_eq!((total.rays, total.hits["quad"]), (20, 6));
+    }
+}
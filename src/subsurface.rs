@@ -0,0 +1,265 @@
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use crate::aabb::AABB;
+use crate::color::Color;
+use crate::hittable::*;
+use crate::interval::Interval;
+use crate::libs::*;
+use crate::material::Material;
+use crate::microfacet::fresnel_dielectric;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::spectrum;
+use crate::vec3::Vec3;
+
+// steps before russian roulette may end a walk
+const ROULETTE_START: i32 = 8;
+
+// participating medium inside a subsurface object. coefficients are per
+// color channel, in 1 / world units
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    pub sigma_s: Color,
+    pub sigma_a: Color,
+    // henyey-greenstein anisotropy, > 0 scatters forward
+    pub g: f64,
+}
+
+impl Medium {
+    // from the color of a thick slab (single scattering albedo) and the
+    // mean distance between scattering events per channel
+    pub fn from_albedo(albedo: &Color, mean_free_path: &Color, g: f64) -> Self {
+        let sigma_t = Color::new(1.0 / mean_free_path.x(), 1.0 / mean_free_path.y(), 1.0 / mean_free_path.z());
+        let sigma_s = *albedo * sigma_t;
+        Self { sigma_s, sigma_a: sigma_t - sigma_s, g }
+    }
+
+    pub fn skin() -> Self {
+        Self::from_albedo(&Color::new(0.95, 0.75, 0.6), &Color::new(0.12, 0.05, 0.03), 0.0)
+    }
+
+    pub fn marble() -> Self {
+        Self::from_albedo(&Color::new(0.99, 0.98, 0.96), &Color::new(0.08, 0.07, 0.06), 0.0)
+    }
+
+    pub fn wax() -> Self {
+        Self::from_albedo(&Color::new(0.98, 0.9, 0.7), &Color::new(0.2, 0.15, 0.1), 0.3)
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        self.sigma_s.hash_into(state);
+        self.sigma_a.hash_into(state);
+        state.write_u64(self.g.to_bits());
+    }
+}
+
+// cosine between the old and new direction for a henyey-greenstein phase
+// function with anisotropy g
+pub fn sample_henyey_greenstein(g: f64) -> f64 {
+    let xi = rand_double();
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * xi;
+    }
+    let s = (1.0 - g*g) / (1.0 - g + 2.0 * g * xi);
+    ((1.0 + g*g - s*s) / (2.0 * g)).clamp(-1.0, 1.0)
+}
+
+// a closed shape filled with a scattering medium behind a smooth dielectric
+// boundary. light that gets in random walks through the medium, using the
+// boundary's own intersection to find where it leaves again. any closed
+// shape works, a Sphere or a watertight Mesh. the boundary is used as is,
+// so it has to be in world space (not inside Animated)
+pub struct Subsurface {
+    boundary: Rc<dyn Hittable>,
+    mat: Rc<dyn Material>,
+}
+
+impl Subsurface {
+    pub fn new(boundary: Rc<dyn Hittable>, medium: Medium, ior: f64) -> Self {
+        let mat = Rc::new(RandomWalk { boundary: boundary.clone(), medium, ior });
+        Self { boundary, mat }
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.boundary.hit(r, ray_t, rec) {
+            return false;
+        }
+        rec.mat = self.mat.clone();
+        return true;
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.boundary.bounding_box()
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"subsurface");
+        self.boundary.hash_into(state);
+        self.mat.hash_into(state);
+    }
+}
+
+struct RandomWalk {
+    boundary: Rc<dyn Hittable>,
+    medium: Medium,
+    ior: f64,
+}
+
+impl RandomWalk {
+    // coefficients for this ray, the same in every channel for a spectral one
+    fn coefficients(&self, r: &Ray) -> (Color, Color) {
+        let sigma_t = self.medium.sigma_s + self.medium.sigma_a;
+        match r.wavelength() {
+            Some(lambda) => {
+                let s = spectrum::rgb_to_spectrum(&self.medium.sigma_s, lambda);
+                let t = spectrum::rgb_to_spectrum(&sigma_t, lambda);
+                (Color::new(s, s, s), Color::new(t, t, t))
+            }
+            None => (self.medium.sigma_s, sigma_t),
+        }
+    }
+
+    fn transmittance(sigma_t: &Color, distance: f64) -> Color {
+        Color::new(f64::exp(-sigma_t.x() * distance), f64::exp(-sigma_t.y() * distance), f64::exp(-sigma_t.z() * distance))
+    }
+
+    fn average(c: &Color) -> f64 {
+        (c.x() + c.y() + c.z()) / 3.0
+    }
+}
+
+impl Material for RandomWalk {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+        // rays only start inside through the walk below
+        if !rec.front_face { return false; }
+
+        let unit_dir = r_in.direction().unit_vector();
+        let cos_i = (-unit_dir).dot(&rec.normal);
+        if rand_double() < fresnel_dielectric(cos_i, self.ior) {
+            let dir = Vec3::reflect(&unit_dir, &rec.normal);
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            *scattered = r_in.spawn(rec.offset_origin(&dir), dir);
+            return true;
+        }
+
+        let (sigma_s, sigma_t) = self.coefficients(r_in);
+        // distances are sampled for one channel picked per walk. the path
+        // is weighted by its pdf averaged over all three channels, and both
+        // running products are kept normalized by the sampled channel's pdf
+        let channel = rand_int(0, 2) as usize;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut pdf = Color::new(1.0, 1.0, 1.0);
+        let mut dir = Vec3::refract(&unit_dir, &rec.normal, 1.0 / self.ior).unit_vector();
+        let mut p = rec.offset_origin(&dir);
+
+        for step in 0.. {
+            // end walks that carry little weight, the survivors make up
+            // for them. capped below 1 so the walk ends in a medium that
+            // hardly absorbs, too
+            if step >= ROULETTE_START {
+                let weight = throughput / Self::average(&pdf);
+                let survive = f64::min(0.99, f64::max(weight.x(), f64::max(weight.y(), weight.z())));
+                if rand_double() >= survive { return false; }
+                throughput /= survive;
+            }
+
+            let mut exit = HitRecord::default();
+            if !self.boundary.hit(&r_in.spawn(p, dir), Interval::new(1e-6, INFINITY), &mut exit) {
+                // leaked through a crack in the boundary
+                return false;
+            }
+
+            let distance = -f64::ln(1.0 - rand_double()) / sigma_t[channel];
+
+            if distance < exit.t {
+                let tr = Self::transmittance(&sigma_t, distance);
+                let norm = sigma_t[channel] * tr[channel];
+                throughput = throughput * sigma_s * tr / norm;
+                pdf = pdf * sigma_t * tr / norm;
+
+                p += dir * distance;
+                let cos_theta = sample_henyey_greenstein(self.medium.g);
+                let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta*cos_theta));
+                let phi = 2.0 * PI * rand_double();
+                dir = Onb::from_normal(&dir).to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+                continue;
+            }
+
+            let tr = Self::transmittance(&sigma_t, exit.t);
+            throughput = throughput * tr / tr[channel];
+            pdf = pdf * tr / tr[channel];
+
+            // at the boundary from inside, the normal faces back in
+            let cos_i = (-dir).dot(&exit.normal);
+            if rand_double() < fresnel_dielectric(cos_i, 1.0 / self.ior) {
+                dir = Vec3::reflect(&dir, &exit.normal);
+                p = exit.offset_origin(&dir);
+                continue;
+            }
+
+            let out = Vec3::refract(&dir, &exit.normal, self.ior);
+            *attenuation = throughput / Self::average(&pdf);
+            *scattered = r_in.spawn(exit.offset_origin(&out), out);
+            return true;
+        }
+
+        unreachable!()
+    }
+
+    // the walk leaves from where it exits the boundary
+    fn places_scattered(&self, _rec: &HitRecord) -> bool {
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        let sigma_t = self.medium.sigma_s + self.medium.sigma_a;
+        Color::new(self.medium.sigma_s.x() / sigma_t.x(), self.medium.sigma_s.y() / sigma_t.y(), self.medium.sigma_s.z() / sigma_t.z())
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"random_walk");
+        self.boundary.hash_into(state);
+        self.medium.hash_into(state);
+        state.write_u64(self.ior.to_bits());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::mesh::Mesh;
+
+    #[test]
+    fn walks_leave_a_closed_mesh_through_its_surface() {
+        let unused = Rc::new(Lambertian::new(&Color::new(0.0, 0.0, 0.0)));
+        let boundary = Rc::new(Mesh::cube(&Vec3::new(0.0, 0.0, 0.0), 1.0, unused));
+        let marble = Subsurface::new(boundary, Medium::marble(), 1.4);
+
+        let r_in = Ray::with_time(Vec3::new(0.1, 0.2, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::default();
+        assert!(marble.hit(&r_in, Interval::new(0.001, INFINITY), &mut rec));
+        assert!(rec.mat.places_scattered(&rec));
+
+        let mut exits = 0;
+        for _ in 0..200 {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            if !rec.mat.scatter(&r_in, &rec, &mut attenuation, &mut scattered) { continue; }
+            exits += 1;
+
+            // just outside one of the faces and heading away from the cube
+            let o = scattered.origin();
+            let extent = f64::max(o.x().abs(), f64::max(o.y().abs(), o.z().abs()));
+            assert!(extent > 0.5 && extent < 0.5 + 1e-3, "{:?}", o);
+            let mut again = HitRecord::default();
+            assert!(!marble.hit(&scattered, Interval::new(1e-6, INFINITY), &mut again), "{:?}", scattered.direction());
+            assert!(attenuation.x() >= 0.0 && attenuation.y() >= 0.0 && attenuation.z() >= 0.0);
+        }
+        // marble hardly absorbs, most light comes back out
+        assert!(exits > 100, "{}", exits);
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+use std::hash::Hasher;
+use std::io::{Error, ErrorKind};
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::tonemap::luminance;
+use crate::vec3::Vec3;
+
+pub trait Texture {
+    // linear color at surface coordinates u, v / hit point p
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color;
+
+    // coverage for cutout masks, 1 is opaque
+    fn alpha(&self, _u: f64, _v: f64, _p: &Vec3) -> f64 {
+        1.0
+    }
+
+    // texels across u and v, None for procedural textures. bump maps take
+    // their finite differences at this resolution
+    fn resolution(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    // see Hittable::hash_into
+    fn hash_into(&self, state: &mut dyn Hasher);
+}
+
+// cutout mask for shapes: where the texture's alpha is below threshold the
+// surface is not there and rays pass through
+#[derive(Clone)]
+pub struct AlphaMask {
+    texture: Rc<dyn Texture>,
+    threshold: f64,
+}
+
+impl AlphaMask {
+    pub fn new(texture: Rc<dyn Texture>, threshold: f64) -> Self {
+        Self { texture, threshold }
+    }
+
+    pub fn opaque(&self, u: f64, v: f64, p: &Vec3) -> bool {
+        self.texture.alpha(u, v, p) >= self.threshold
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"mask");
+        self.texture.hash_into(state);
+        state.write_u64(self.threshold.to_bits());
+    }
+}
+
+// uses a texture's luminance as alpha, e.g. a checkerboard for a fence
+// or a grayscale opacity map
+pub struct LuminanceAlpha {
+    texture: Rc<dyn Texture>,
+}
+
+impl LuminanceAlpha {
+    pub fn new(texture: Rc<dyn Texture>) -> Self {
+        Self { texture }
+    }
+}
+
+impl Texture for LuminanceAlpha {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        self.texture.value(u, v, p)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: &Vec3) -> f64 {
+        luminance(&self.texture.value(u, v, p))
+    }
+
+    fn resolution(&self) -> Option<(usize, usize)> {
+        self.texture.resolution()
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"luminance_alpha");
+        self.texture.hash_into(state);
+    }
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: &Color) -> Self {
+        Self { albedo: *albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+        self.albedo
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"solid");
+        self.albedo.hash_into(state);
+    }
+}
+
+// grey value for scalar material parameters
+pub fn constant(value: f64) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::new(&Color::new(value, value, value)))
+}
+
+pub fn solid(color: &Color) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::new(color))
+}
+
+// 3d checkerboard of cells `scale` wide
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> Self {
+        Self { inv_scale: 1.0 / scale, even, odd }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let x = f64::floor(self.inv_scale * p.x()) as i64;
+        let y = f64::floor(self.inv_scale * p.y()) as i64;
+        let z = f64::floor(self.inv_scale * p.z()) as i64;
+
+        if (x + y + z) % 2 == 0 { self.even.value(u, v, p) } else { self.odd.value(u, v, p) }
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"checker");
+        state.write_u64(self.inv_scale.to_bits());
+        self.even.hash_into(state);
+        self.odd.hash_into(state);
+    }
+}
+
+// smooth egg crate pattern between 0 and 1 with period 2 pi scale, handy
+// as a procedural bump map
+pub struct WaveTexture {
+    inv_scale: f64,
+}
+
+impl WaveTexture {
+    pub fn new(scale: f64) -> Self {
+        Self { inv_scale: 1.0 / scale }
+    }
+}
+
+impl Texture for WaveTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        let q = *p * self.inv_scale;
+        let h = 0.5 + 0.5 * q.x().sin() * q.y().sin() * q.z().sin();
+        Color::new(h, h, h)
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"wave");
+        state.write_u64(self.inv_scale.to_bits());
+    }
+}
+
+// ppm image (P3 or P6), or pam (P7) with an alpha channel, looked up by
+// u, v with v = 0 at the bottom row. color images are stored srgb encoded,
+// data textures (roughness, normal maps ...) are linear. alpha is always
+// linear
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    alpha: Vec<f64>,
+}
+
+impl ImageTexture {
+    pub fn load(path: &str, srgb: bool) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        if data.starts_with(b"P7") {
+            return Self::load_pam(path, &data, srgb);
+        }
+        Self::load_ppm(path, &data, srgb)
+    }
+
+    fn load_ppm(path: &str, data: &[u8], srgb: bool) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+        // header is whitespace separated, with # comments
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 && pos < data.len() {
+            match data[pos] {
+                b'#' => while pos < data.len() && data[pos] != b'\n' { pos += 1; },
+                c if c.is_ascii_whitespace() => pos += 1,
+                _ => {
+                    let start = pos;
+                    while pos < data.len() && !data[pos].is_ascii_whitespace() { pos += 1; }
+                    fields.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+                }
+            }
+        }
+        if fields.len() < 4 { return Err(invalid("truncated header")); }
+
+        let number = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad header"));
+        let (width, height, max) = (number(&fields[1])?, number(&fields[2])?, number(&fields[3])?);
+        let sample_count = Self::check_header(width, height, 3, max).map_err(invalid)?;
+        let max = max as f64;
+
+        let samples: Vec<f64> = match fields[0].as_str() {
+            "P3" => String::from_utf8_lossy(&data[pos..]).split_ascii_whitespace()
+                        .map(|s| s.parse::<f64>().map_err(|_| invalid("bad sample")))
+                        .collect::<Result<_, _>>()?,
+            // a single whitespace byte separates the header from binary data
+            "P6" => {
+                let body = data.get(pos + 1..).ok_or_else(|| invalid("truncated header"))?;
+                if max < 256.0 {
+                    body.iter().map(|&b| b as f64).collect()
+                } else {
+                    body.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f64).collect()
+                }
+            }
+            _ => return Err(invalid("not a ppm file")),
+        };
+        if samples.len() < sample_count { return Err(invalid("truncated pixel data")); }
+
+        let decode = |s: f64| Self::decode(s / max, srgb);
+        let pixels = samples.chunks_exact(3).take(width * height)
+                            .map(|c| Color::new(decode(c[0]), decode(c[1]), decode(c[2])))
+                            .collect();
+
+        Ok(Self { width, height, pixels, alpha: vec![1.0; width * height] })
+    }
+
+    // binary PAM with a "KEY value" header, TUPLTYPE RGB_ALPHA,
+    // GRAYSCALE_ALPHA, RGB or GRAYSCALE
+    fn load_pam(path: &str, data: &[u8], srgb: bool) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+        let end = data.windows(7).position(|w| w == b"ENDHDR\n").ok_or_else(|| invalid("missing ENDHDR"))?;
+        let header = String::from_utf8_lossy(&data[..end]);
+        let body = &data[end + 7..];
+
+        let (mut width, mut height, mut depth, mut max) = (0, 0, 0, 255);
+        for line in header.lines().skip(1) {
+            let mut words = line.split_whitespace();
+            let (Some(key), Some(value)) = (words.next(), words.next()) else { continue; };
+            let value = || value.parse::<usize>().map_err(|_| invalid("bad header"));
+            match key {
+                "WIDTH"  => width = value()?,
+                "HEIGHT" => height = value()?,
+                "DEPTH"  => depth = value()?,
+                "MAXVAL" => max = value()?,
+                _ => {}
+            }
+        }
+        if !(1..=4).contains(&depth) { return Err(invalid("unsupported depth")); }
+        let sample_count = Self::check_header(width, height, depth, max).map_err(invalid)?;
+
+        let samples: Vec<f64> = if max < 256 {
+            body.iter().map(|&b| b as f64 / max as f64).collect()
+        } else {
+            body.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / max as f64).collect()
+        };
+        if samples.len() < sample_count { return Err(invalid("truncated pixel data")); }
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut alpha = Vec::with_capacity(width * height);
+        for s in samples.chunks_exact(depth).take(width * height) {
+            let (color, a) = match s {
+                [g] => (Color::new(*g, *g, *g), 1.0),
+                [g, a] => (Color::new(*g, *g, *g), *a),
+                [r, g, b] => (Color::new(*r, *g, *b), 1.0),
+                [r, g, b, a, ..] => (Color::new(*r, *g, *b), *a),
+                _ => unreachable!(),
+            };
+            pixels.push(Color::new(Self::decode(color.x(), srgb), Self::decode(color.y(), srgb), Self::decode(color.z(), srgb)));
+            alpha.push(a);
+        }
+
+        Ok(Self { width, height, pixels, alpha })
+    }
+
+    // number of samples in the pixel data. samples are one or two bytes,
+    // so maxval has to be in 1..=65535
+    fn check_header(width: usize, height: usize, channels: usize, max: usize) -> Result<usize, &'static str> {
+        if max == 0 || max > 65535 { return Err("maxval out of range"); }
+        width.checked_mul(height).and_then(|n| n.checked_mul(channels)).ok_or("image too large")
+    }
+
+    fn decode(c: f64, srgb: bool) -> f64 {
+        if !srgb { c }
+        else if c <= 0.04045 { c / 12.92 }
+        else { f64::powf((c + 0.055) / 1.055, 2.4) }
+    }
+
+    // repeat outside [0, 1], bilinear between pixel centers so bump maps
+    // get smooth derivatives
+    fn sample<T>(&self, buffer: &[T], u: f64, v: f64) -> T
+    where T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f64, Output = T> {
+        let x = (u - u.floor()) * self.width as f64 - 0.5;
+        let y = (1.0 - (v - v.floor())) * self.height as f64 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+
+        let texel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(self.width as i64) as usize;
+            let j = (j as i64).clamp(0, self.height as i64 - 1) as usize;
+            buffer[j * self.width + i]
+        };
+        let (x0, y0) = (x.floor(), y.floor());
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Color {
+        if self.width == 0 || self.height == 0 { return Color::new(0.0, 1.0, 1.0); }
+        self.sample(&self.pixels, u, v)
+    }
+
+    fn alpha(&self, u: f64, v: f64, _p: &Vec3) -> f64 {
+        if self.width == 0 || self.height == 0 { return 1.0; }
+        self.sample(&self.alpha, u, v)
+    }
+
+    fn resolution(&self) -> Option<(usize, usize)> {
+        Some((self.width, self.height))
+    }
+
+    fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"image");
+        state.write_usize(self.width);
+        state.write_usize(self.height);
+        for c in &self.pixels {
+            c.hash_into(state);
+        }
+        for a in &self.alpha {
+            state.write_u64(a.to_bits());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pam(header: &str, body: &[u8]) -> Result<ImageTexture, Error> {
+        let mut data = format!("P7\n{}\nENDHDR\n", header).into_bytes();
+        data.extend_from_slice(body);
+        ImageTexture::load_pam("test.pam", &data, false)
+    }
+
+    #[test]
+    fn pam_with_alpha() {
+        let image = pam("WIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA", &[255, 0, 0, 255, 0, 0, 255, 0]).unwrap();
+        assert_eq!(image.resolution(), Some((2, 1)));
+        assert_eq!(image.pixels[0].x(), 1.0);
+        assert_eq!(image.pixels[1].z(), 1.0);
+        assert_eq!(image.alpha, [1.0, 0.0]);
+
+        let image = pam("WIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 65535", &[0x80, 0x00, 0xff, 0xff]).unwrap();
+        assert!((image.pixels[0].y() - 32768.0 / 65535.0).abs() < 1e-12);
+        assert_eq!(image.alpha, [1.0]);
+    }
+
+    #[test]
+    fn bad_pam_headers_are_rejected() {
+        for header in ["WIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 0", "WIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 65536",
+                       "WIDTH 1\nHEIGHT 1\nDEPTH 5\nMAXVAL 255", "WIDTH x\nHEIGHT 1\nDEPTH 1\nMAXVAL 255",
+                       "WIDTH 4294967296\nHEIGHT 4294967296\nDEPTH 4\nMAXVAL 255",
+                       "WIDTH 2\nHEIGHT 2\nDEPTH 3\nMAXVAL 255"] {
+            assert!(pam(header, &[0; 8]).is_err(), "{:?}", header);
+        }
+        assert!(ImageTexture::load_pam("test.pam", b"P7\nWIDTH 1\n", false).is_err());
+    }
+
+    #[test]
+    fn bad_ppm_headers_are_rejected() {
+        for data in [&b"P6 1 1 255"[..], b"P6 1 1", b"P6 1 1 255\n", b"P3 1 1 255\n0 0", b"P5 1 1 255\n\0"] {
+            assert!(ImageTexture::load_ppm("test.ppm", data, false).is_err(), "{:?}", data);
+        }
+        assert!(ImageTexture::load_ppm("test.ppm", b"P6 1 1 255\n\xff\0\0", false).is_ok());
+    }
+
+    #[test]
+    fn luminance_alpha_weights_the_channels() {
+        let mask = LuminanceAlpha::new(solid(&Color::new(0.0, 1.0, 0.0)));
+        let alpha = mask.alpha(0.0, 0.0, &Vec3::default());
+        assert!((alpha - luminance(&Color::new(0.0, 1.0, 0.0))).abs() < 1e-12);
+        assert!(alpha < 1.0);
+    }
+}
@@ -0,0 +1,156 @@
+use std::hash::Hasher;
+
+use crate::color::Color;
+use crate::libs::PI;
+use crate::spectrum;
+
+// wavelengths, nm, standing in for the r, g and b channels in rgb mode
+const LAMBDA_RGB: [f64; 3] = [650.0, 550.0, 450.0];
+
+// what lies under the film
+#[derive(Clone, Copy, Debug)]
+pub enum Substrate {
+    Dielectric(f64),
+    // metal with the given normal incidence reflectance per channel. the
+    // interface is taken to flip the phase and not depend on the angle
+    Conductor(Color),
+}
+
+// thin transparent layer (oil on water, soap, anti-reflection coatings,
+// heat tint) whose reflections interfere depending on thickness, angle and
+// wavelength
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    // nm
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+
+    // reflectance per channel coming from a medium of index n_outside at
+    // cos_i to the normal. a spectral ray (lambda) gets the same value in
+    // every channel
+    pub fn reflectance(&self, cos_i: f64, n_outside: f64, substrate: &Substrate, lambda: Option<f64>) -> Color {
+        match lambda {
+            Some(l) => {
+                // a conductor's color as its reflectance at this wavelength
+                let substrate = match substrate {
+                    Substrate::Conductor(f0) => {
+                        let f = spectrum::rgb_to_spectrum(f0, l);
+                        Substrate::Conductor(Color::new(f, f, f))
+                    }
+                    dielectric => *dielectric,
+                };
+                let r = self.reflectance_at(cos_i, n_outside, &substrate, l, 0);
+                Color::new(r, r, r)
+            }
+            None => Color::new(self.reflectance_at(cos_i, n_outside, substrate, LAMBDA_RGB[0], 0),
+                               self.reflectance_at(cos_i, n_outside, substrate, LAMBDA_RGB[1], 1),
+                               self.reflectance_at(cos_i, n_outside, substrate, LAMBDA_RGB[2], 2)),
+        }
+    }
+
+    // airy summation over the multiple reflections inside the film, for s
+    // and p polarization with real amplitudes, averaged
+    fn reflectance_at(&self, cos_i: f64, n_outside: f64, substrate: &Substrate, lambda: f64, channel: usize) -> f64 {
+        let cos_1 = cos_i.clamp(0.0, 1.0);
+        let (n1, n2) = (n_outside, self.ior);
+
+        let sin2_2 = (n1 / n2).powi(2) * (1.0 - cos_1*cos_1);
+        if sin2_2 >= 1.0 { return 1.0; }
+        let cos_2 = f64::sqrt(1.0 - sin2_2);
+
+        let (r12_s, r12_p) = amplitudes(n1, cos_1, n2, cos_2);
+        let (r23_s, r23_p) = match substrate {
+            Substrate::Dielectric(n3) => {
+                let sin2_3 = (n2 / n3).powi(2) * sin2_2;
+                if sin2_3 >= 1.0 {
+                    (1.0, 1.0)
+                } else {
+                    amplitudes(n2, cos_2, *n3, f64::sqrt(1.0 - sin2_3))
+                }
+            }
+            Substrate::Conductor(f0) => {
+                let r = -f0[channel].clamp(0.0, 1.0).sqrt();
+                (r, r)
+            }
+        };
+
+        // phase difference of one round trip through the film
+        let delta = 4.0 * PI * n2 * self.thickness * cos_2 / lambda;
+        let airy = |a: f64, b: f64| {
+            let c = 2.0 * a * b * delta.cos();
+            ((a*a + b*b + c) / (1.0 + a*a*b*b + c)).clamp(0.0, 1.0)
+        };
+
+        (airy(r12_s, r23_s) + airy(r12_p, r23_p)) / 2.0
+    }
+
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        state.write(b"film");
+        state.write_u64(self.thickness.to_bits());
+        state.write_u64(self.ior.to_bits());
+    }
+}
+
+// fresnel amplitude coefficients (s, p) going from index n1 to n2
+fn amplitudes(n1: f64, cos_1: f64, n2: f64, cos_2: f64) -> (f64, f64) {
+    let s = (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2);
+    let p = (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2);
+    (s, p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vanishing_film_is_the_bare_interface() {
+        // fresnel at normal incidence from air onto glass, ((n-1)/(n+1))^2
+        let film = ThinFilm::new(0.0, 1.33);
+        let r = film.reflectance(1.0, 1.0, &Substrate::Dielectric(1.5), None);
+        assert!((r.x() - 0.04).abs() < 1e-9 && (r.z() - 0.04).abs() < 1e-9, "{:?}", r);
+
+        // and a film matching the outside leaves the metal's own color
+        let gold = Substrate::Conductor(Color::new(1.0, 0.78, 0.34));
+        let r = ThinFilm::new(120.0, 1.0).reflectance(1.0, 1.0, &gold, None);
+        assert!((r.x() - 1.0).abs() < 1e-9 && (r.y() - 0.78).abs() < 1e-9 && (r.z() - 0.34).abs() < 1e-9, "{:?}", r);
+    }
+
+    #[test]
+    fn quarter_wave_coating_cancels_the_reflection() {
+        // n = sqrt(1.5) and a quarter wavelength thick at 550nm
+        let n = f64::sqrt(1.5);
+        let film = ThinFilm::new(550.0 / (4.0 * n), n);
+        let r = film.reflectance(1.0, 1.0, &Substrate::Dielectric(1.5), None);
+        assert!(r.y() < 1e-9, "{:?}", r);
+        assert!(r.x() > 1e-4 && r.z() > 1e-4, "{:?}", r);
+        // thicker by half a wave it reflects like bare glass again
+        let film = ThinFilm::new(3.0 * 550.0 / (4.0 * n) + 550.0 / (4.0 * n), n);
+        assert!((film.reflectance(1.0, 1.0, &Substrate::Dielectric(1.5), None).y() - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spectral_conductor_follows_its_color() {
+        let film = ThinFilm::new(120.0, 1.0);
+        let copper = Substrate::Conductor(Color::new(0.95, 0.64, 0.54));
+        for lambda in [450.0, 550.0, 650.0] {
+            let r = film.reflectance(1.0, 1.0, &copper, Some(lambda));
+            let expected = spectrum::rgb_to_spectrum(&Color::new(0.95, 0.64, 0.54), lambda);
+            assert!((r.x() - expected).abs() < 1e-9 && r.x() == r.z(), "{} {:?}", lambda, r);
+        }
+        // blue light sees the copper's blue, not its red
+        assert!(film.reflectance(1.0, 1.0, &copper, Some(450.0)).x() < 0.6);
+    }
+
+    #[test]
+    fn total_internal_reflection_in_the_film() {
+        let film = ThinFilm::new(300.0, 1.2);
+        let r = film.reflectance(0.1, 1.5, &Substrate::Dielectric(1.0), Some(550.0));
+        assert_eq!(r.x(), 1.0);
+    }
+}
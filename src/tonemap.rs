@@ -0,0 +1,60 @@
+use crate::color::Color;
+
+// maps linear hdr radiance into [0, 1] before the srgb transfer
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    Reinhard,
+    // luminance that maps to pure white
+    ExtendedReinhard(f64),
+    Aces,
+}
+
+impl ToneMap {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "clamp"    => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "aces"     => Some(ToneMap::Aces),
+            _ => {
+                // extended-reinhard[:white]
+                let white = name.strip_prefix("extended-reinhard")?;
+                match white.strip_prefix(':') {
+                    Some(w) => w.parse().ok().map(ToneMap::ExtendedReinhard),
+                    None if white.is_empty() => Some(ToneMap::ExtendedReinhard(4.0)),
+                    None => None,
+                }
+            }
+        }
+    }
+
+    pub fn apply(&self, c: &Color) -> Color {
+        match self {
+            ToneMap::Clamp => *c,
+            ToneMap::Reinhard => Self::scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard(white) => {
+                let w2 = white * white;
+                Self::scale_luminance(c, |l| l * (1.0 + l / w2) / (1.0 + l))
+            }
+            ToneMap::Aces => Color::new(Self::aces(c.x()), Self::aces(c.y()), Self::aces(c.z())),
+        }
+    }
+
+    // reinhard variants operate on luminance so hue is preserved
+    fn scale_luminance(c: &Color, f: impl Fn(f64) -> f64) -> Color {
+        let l = luminance(c);
+        if l <= 0.0 { return Color::default(); }
+        *c * (f(l) / l)
+    }
+
+    // Narkowicz's fit of the ACES filmic curve
+    fn aces(x: f64) -> f64 {
+        let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+        (x * (a*x + b)) / (x * (c*x + d) + e)
+    }
+}
+
+pub fn luminance(c: &Color) -> f64 {
+    0.2126*c.x() + 0.7152*c.y() + 0.0722*c.z()
+}
@@ -1,3 +1,5 @@
+use std::hash::Hasher;
+
 use crate::libs::*;
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -20,6 +22,13 @@ impl Vec3 {
         self.e[2]
     }        
 
+    // bit patterns of the components, for scene hashes
+    pub fn hash_into(&self, state: &mut dyn Hasher) {
+        for x in self.e {
+            state.write_u64(x.to_bits());
+        }
+    }
+
     pub fn length(&self) -> f64 {
         f64::sqrt(self.length_squared())
     }
@@ -68,6 +77,21 @@ impl Vec3 {
         }
     }
 
+    // uniform point in a regular polygon inscribed in the unit circle,
+    // rotated by rotation radians
+    pub fn random_in_polygon(sides: u32, rotation: f64) -> Self {
+        // pick one of the triangles fanning out from the center, then a
+        // uniform point inside it
+        let wedge = 2.0 * PI / sides as f64;
+        let k = rand_int(0, sides as i32 - 1) as f64;
+        let a0 = rotation + k * wedge;
+        let (v0, v1) = (Vec3::new(a0.cos(), a0.sin(), 0.0), Vec3::new((a0 + wedge).cos(), (a0 + wedge).sin(), 0.0));
+
+        let (mut s, mut t) = (rand_double(), rand_double());
+        if s + t > 1.0 { s = 1.0 - s; t = 1.0 - t; }
+        s * v0 + t * v1
+    }
+
     pub fn reflect(v: &Self, n: &Self) -> Self {
         (*v) - (2.0*v.dot(n)*(*n))
     }
@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::{stderr, BufWriter, Write, Error};
+use std::thread;
+use std::time::Duration;
+
+use show_image::event::*;
+
+use crate::camera::{self, Camera};
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::vec3::Vec3;
+
+// interactive mode: mouse / keyboard move the camera and every move
+// restarts a progressive render from 1 spp.
+//
+//   left drag / arrows      orbit around lookat
+//   right drag              pan
+//   wheel / w, s            dolly
+//   q, e                    narrower / wider field of view
+//   c                       show what is in the middle of the view and
+//                           focus on it
+//   p                       print camera settings as scene code
+//   f                       save the current frame
+//   escape                  quit
+pub fn run<H: Hittable>(cam: &mut Camera, world: &H, scene_name: &str) -> Result<(), Error> {
+    cam.initialize(world);
+    cam.open_window(false);
+
+    let mut film = Film::new(cam.img_width, cam.img_height());
+    let mut pass = 0;
+    let mut saved_frames = 0;
+
+    loop {
+        let mut moved = false;
+        while let Some(event) = cam.window_events().and_then(|events| events.try_recv().ok()) {
+            match event {
+                WindowEvent::CloseRequested(_) | WindowEvent::Destroyed(_) => return Ok(()),
+                WindowEvent::KeyboardInput(e) if e.input.state == ElementState::Pressed => {
+                    let Some(key) = e.input.key_code else { continue; };
+                    match key {
+                        VirtualKeyCode::Escape => return Ok(()),
+                        VirtualKeyCode::Left  => { orbit(cam, -0.1, 0.0); moved = true; }
+                        VirtualKeyCode::Right => { orbit(cam, 0.1, 0.0); moved = true; }
+                        VirtualKeyCode::Up    => { orbit(cam, 0.0, 0.1); moved = true; }
+                        VirtualKeyCode::Down  => { orbit(cam, 0.0, -0.1); moved = true; }
+                        VirtualKeyCode::W => { dolly(cam, 0.9); moved = true; }
+                        VirtualKeyCode::S => { dolly(cam, 1.0 / 0.9); moved = true; }
+                        VirtualKeyCode::Q => { zoom(cam, 0.9); moved = true; }
+                        VirtualKeyCode::E => { zoom(cam, 1.0 / 0.9); moved = true; }
+                        VirtualKeyCode::C => moved |= focus_center(cam, world)?,
+                        VirtualKeyCode::P => print_camera(cam, scene_name),
+                        VirtualKeyCode::F => {
+                            saved_frames += 1;
+                            save_frame(cam, &film, &format!("{}_{:04}.ppm", scene_name, saved_frames))?;
+                        }
+                        _ => {}
+                    }
+                }
+                WindowEvent::MouseMove(e) => {
+                    let dx = (e.position.x - e.prev_position.x) as f64;
+                    let dy = (e.position.y - e.prev_position.y) as f64;
+                    if e.buttons.is_pressed(MouseButton::Left) {
+                        orbit(cam, -dx * 0.01, dy * 0.01);
+                        moved = true;
+                    } else if e.buttons.is_pressed(MouseButton::Right) {
+                        pan(cam, dx, dy);
+                        moved = true;
+                    }
+                }
+                WindowEvent::MouseWheel(e) => {
+                    let lines = match e.delta {
+                        MouseScrollDelta::LineDelta(_, y) => y as f64,
+                        _ => 0.0,
+                    };
+                    if lines != 0.0 {
+                        dolly(cam, f64::powf(0.9, lines));
+                        moved = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if moved {
+            cam.initialize(world);
+            film = Film::new(cam.img_width, cam.img_height());
+            pass = 0;
+        }
+
+        if camera::interrupted() { return Ok(()); }
+
+        if pass < cam.samples_per_pixel {
+            cam.render_pass(world, &mut film, &mut None);
+            cam.display(&film);
+            pass += 1;
+        } else {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// rotate v around a unit axis by angle radians (Rodrigues)
+fn rotate(v: &Vec3, axis: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    *v * cos + axis.cross(v) * sin + *axis * (axis.dot(v) * (1.0 - cos))
+}
+
+// yaw around vup, pitch around the camera's horizontal axis
+fn orbit(cam: &mut Camera, yaw: f64, pitch: f64) {
+    let up = cam.vup.unit_vector();
+    let mut offset = rotate(&(cam.lookfrom - cam.lookat), &up, yaw);
+
+    let right = up.cross(&offset).unit_vector();
+    let pitched = rotate(&offset, &right, -pitch);
+    // don't flip over the poles
+    if pitched.unit_vector().dot(&up).abs() < 0.99 {
+        offset = pitched;
+    }
+
+    cam.lookfrom = cam.lookat + offset;
+}
+
+// move both lookfrom and lookat across the view plane, dx / dy in pixels
+fn pan(cam: &mut Camera, dx: f64, dy: f64) {
+    let offset = cam.lookfrom - cam.lookat;
+    let w = offset.unit_vector();
+    let u = cam.vup.cross(&w).unit_vector();
+    let v = w.cross(&u);
+
+    // roughly keep the point under the cursor under the cursor
+    let scale = offset.length() * 2.0 * f64::tan(cam.active_vfov().to_radians() / 2.0) / cam.img_height() as f64;
+    let delta = (-dx * scale) * u + (dy * scale) * v;
+    cam.lookfrom += delta;
+    cam.lookat += delta;
+}
+
+fn dolly(cam: &mut Camera, factor: f64) {
+    let offset = (cam.lookfrom - cam.lookat) * factor;
+    if offset.length() > 1e-3 {
+        cam.lookfrom = cam.lookat + offset;
+    }
+}
+
+fn zoom(cam: &mut Camera, factor: f64) {
+    match &mut cam.lens {
+        Some(lens) => lens.focal_length /= factor,
+        None => cam.vfov = f64::clamp(cam.vfov * factor, 1.0, 170.0),
+    }
+}
+
+// true if something was there to focus on
+fn focus_center<H: Hittable>(cam: &mut Camera, world: &H) -> Result<bool, Error> {
+    let (i, j) = (cam.img_width / 2, cam.img_height() / 2);
+    let Some(rec) = cam.pick(world, i, j) else {
+        stderr().write_all(b"Nothing in the middle of the view\n")?;
+        return Ok(false);
+    };
+
+    let msg = format!("Object {} at {:?}, albedo {:?}\n", rec.object_id, rec.p, rec.mat.albedo(&rec));
+    stderr().write_all(msg.as_bytes())?;
+    cam.autofocus = Some((i, j));
+    Ok(true)
+}
+
+// same form as the camera setup in scenes.rs so it can be pasted there
+fn print_camera(cam: &Camera, scene_name: &str) {
+    let vec = |v: &Vec3| format!("Vec3::new({:?}, {:?}, {:?})", v.x(), v.y(), v.z());
+
+    println!("// {}", scene_name);
+    println!("cam.vfov = {:?};", cam.vfov);
+    println!("cam.lookfrom = {};", vec(&cam.lookfrom));
+    println!("cam.lookat = {};", vec(&cam.lookat));
+    println!("cam.vup = {};", vec(&cam.vup));
+    println!();
+    println!("cam.defocus_angle = {:?};", cam.defocus_angle);
+    println!("cam.focus_dist = {:?};", cam.focus_dist);
+    if let Some((i, j)) = cam.autofocus {
+        println!("cam.autofocus = Some(({}, {}));", i, j);
+    }
+}
+
+fn save_frame(cam: &Camera, film: &Film, path: &str) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    film.write_ppm(&mut out, cam.display_exposure(), cam.tone_map)?;
+    stderr().write_all(format!("Saved {}\n", path).as_bytes())
+}